@@ -13,3 +13,21 @@ pub const ATTRBYTES_MAX: u32 = 1_022;
 pub type ATTRBYTES_MAX_TYPE = U1022;
 pub const LOOKAHEADWORDS_SIZE: u32 = 16;
 
+/// Attribute id under which `Filesystem::set_content_hash` stores a file's CRC-32.
+pub const CONTENT_HASH_ATTRIBUTE_ID: u8 = 0xfe;
+
+/// Suffix of the temporary files used by `Filesystem::rename_swap`, see `Filesystem::recover_atomic`.
+pub const ATOMIC_TEMP_SUFFIX: &str = ".tmp~";
+
+/// Attribute id under which a file being swapped records the name of its partner.
+pub const SWAP_PARTNER_ATTRIBUTE_ID: u8 = 0xfd;
+
+/// Deepest nesting of directories that the recursive helpers (`Filesystem::walk_dir`,
+/// `remove_dir_all`, `find`) descend into, to bound their stack usage.
+pub const MAX_RECURSION_DEPTH: usize = 8;
+
+/// Attribute id holding a file's little-endian sequence number, see `Filesystem::enforce_budget`.
+pub const SEQUENCE_ATTRIBUTE_ID: u8 = 0xfc;
+
+/// Attribute id under which `Filesystem::set_modified` stores a file's modification time.
+pub const MODIFIED_ATTRIBUTE_ID: u8 = 0xfb;
@@ -8,6 +8,21 @@ use crate::{
     io::Result,
 };
 
+mod checksummed;
+pub use checksummed::ChecksummedStorage;
+mod faulty;
+pub use faulty::FaultyStorage;
+mod partition;
+pub use partition::PartitionStorage;
+mod remap;
+pub use remap::RemapStorage;
+mod slice;
+pub use slice::SliceStorage;
+#[cfg(feature = "std")]
+mod slow;
+#[cfg(feature = "std")]
+pub use slow::SlowStorage;
+
 /// Users of this library provide a "storage driver" by implementing this trait.
 ///
 /// The `write` method is assumed to be synchronized to storage immediately.
@@ -40,6 +55,8 @@ pub trait Storage {
 
     /// Number of erasable blocks.
     /// Hence storage capacity is `BLOCK_COUNT * BLOCK_SIZE`
+    ///
+    /// For storage sized at runtime, an upper bound, see `block_count`.
     const BLOCK_COUNT: usize;
 
     /// Suggested values are 100-1000, higher is more performant but
@@ -47,11 +64,26 @@ pub trait Storage {
     /// Value zero is invalid, must be positive or -1.
     const BLOCK_CYCLES: isize = -1;
 
+    /// Maximum number of files open at the same time, further opens fail with
+    /// `TooManyOpenFiles`. littlefs itself has no limit, but each open file needs
+    /// an allocation with its own cache. Unlimited by default.
+    const MAX_OPEN_FILES: usize = usize::MAX;
+
     /// littlefs uses a read cache, a write cache, and one cache per per file.
     /// Must be a multiple of `READ_SIZE` and `WRITE_SIZE`.
     /// Must be a factor of `BLOCK_SIZE`.
     type CACHE_SIZE: ArrayLength<u8>;
 
+    /// Number of erasable blocks of this storage, between 1 and `BLOCK_COUNT`.
+    ///
+    /// littlefs is configured with this count when mounting or formatting, so storage
+    /// sized at runtime overrides it. Wrappers that lay out blocks at compile time check
+    /// it on construction: `PartitionStorage` must fit into it, and `RemapStorage` rejects
+    /// storage sized at runtime.
+    fn block_count(&self) -> usize {
+        Self::BLOCK_COUNT
+    }
+
     /// littlefs itself has a `LOOKAHEAD_SIZE`, which must be a multiple of 8,
     /// as it stores data in a bitmap. It also asks for 4-byte aligned buffers.
     /// Hence, we further restrict `LOOKAHEAD_SIZE` to be a multiple of 32.
@@ -91,12 +123,15 @@ pub trait Storage {
 
     /// Read data from the storage device.
     /// Guaranteed to be called only with bufs of length a multiple of READ_SIZE.
+    /// Must return the length of `buf`, shorter reads are treated as I/O errors.
     fn read(&self, off: usize, buf: &mut [u8]) -> Result<usize>;
     /// Write data to the storage device.
     /// Guaranteed to be called only with bufs of length a multiple of WRITE_SIZE.
+    /// Must return the length of `data`, shorter writes are treated as I/O errors.
     fn write(&mut self, off: usize, data: &[u8]) -> Result<usize>;
     /// Erase data from the storage device.
     /// Guaranteed to be called only with bufs of length a multiple of BLOCK_SIZE.
+    /// Must return `len`.
     fn erase(&mut self, off: usize, len: usize) -> Result<usize>;
     // /// Synchronize writes to the storage device.
     // fn sync(&mut self) -> Result<usize>;
@@ -0,0 +1,120 @@
+//! Detecting bit rot below the filesystem.
+
+use core::marker::PhantomData;
+
+use generic_array::ArrayLength;
+
+use crate::{
+    crc,
+    driver::Storage,
+    fs::Bytes,
+    io::{Error, Result},
+};
+
+/// Size of the checksum stored with each write unit.
+const CHECKSUM_SIZE: usize = 4;
+
+/// A storage that stores a CRC-32 with every write unit of another storage.
+///
+/// Each `S::WRITE_SIZE` bytes of the underlying storage hold `S::WRITE_SIZE - 4` bytes
+/// of data followed by their checksum, and reads of data whose checksum does not match
+/// fail with `Error::Io`. Both `READ_SIZE` and `WRITE_SIZE` of this storage are the data
+/// size of one unit, and blocks shrink accordingly. The cache size `C` must be a multiple
+/// of the unit data size dividing the block size, e.g. `U28` for a 32 byte write size:
+///
+/// ```ignore
+/// let mut checksummed = ChecksummedStorage::<_, consts::U56>::new(&mut storage);
+/// Filesystem::format(&mut checksummed)?;
+/// ```
+///
+/// Units that were erased but not written since are read as they are. A unit is taken
+/// to be erased if all of its bytes are equal, so a unit corrupted into that state goes
+/// unnoticed.
+pub struct ChecksummedStorage<'s, S: Storage, C> {
+    storage: &'s mut S,
+    cache_size: PhantomData<C>,
+}
+
+impl<'s, S: Storage, C> ChecksummedStorage<'s, S, C> {
+    /// Size of the data in a unit.
+    const DATA_SIZE: usize = S::WRITE_SIZE - CHECKSUM_SIZE;
+
+    /// Panics if the units of `storage` are too small to hold a checksum, or cannot be read
+    /// on their own.
+    pub fn new(storage: &'s mut S) -> Self {
+        assert!(S::WRITE_SIZE > CHECKSUM_SIZE);
+        assert!(S::WRITE_SIZE % S::READ_SIZE == 0);
+        Self { storage, cache_size: PhantomData }
+    }
+
+    /// Release the underlying storage.
+    pub fn into_inner(self) -> &'s mut S {
+        self.storage
+    }
+
+    /// Offset of the unit holding data at `off` in the underlying storage.
+    fn unit_offset(off: usize) -> Result<usize> {
+        if off % Self::DATA_SIZE != 0 {
+            return Err(Error::Invalid);
+        }
+        Ok(off / Self::DATA_SIZE * S::WRITE_SIZE)
+    }
+}
+
+impl<S: Storage, C: ArrayLength<u8>> Storage for ChecksummedStorage<'_, S, C> {
+    const READ_SIZE: usize = Self::DATA_SIZE;
+    const WRITE_SIZE: usize = Self::DATA_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE / S::WRITE_SIZE * Self::DATA_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const MAX_OPEN_FILES: usize = S::MAX_OPEN_FILES;
+    type CACHE_SIZE = C;
+    type LOOKAHEADWORDS_SIZE = S::LOOKAHEADWORDS_SIZE;
+
+    fn block_count(&self) -> usize {
+        self.storage.block_count()
+    }
+
+    fn read(&self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        // the underlying cache size is a multiple of its write size
+        let mut unit: Bytes<S::CACHE_SIZE> = Default::default();
+        let unit = &mut unit[..S::WRITE_SIZE];
+        let mut unit_off = Self::unit_offset(off)?;
+        for data in buf.chunks_mut(Self::DATA_SIZE) {
+            self.storage.read(unit_off, unit)?;
+            let erased = unit.iter().all(|&byte| byte == unit[0]);
+            let (contents, checksum) = unit.split_at(Self::DATA_SIZE);
+            if !erased && crc::crc32(contents).to_le_bytes() != checksum {
+                return Err(Error::Io);
+            }
+            data.copy_from_slice(&contents[..data.len()]);
+            unit_off += S::WRITE_SIZE;
+        }
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        let mut unit: Bytes<S::CACHE_SIZE> = Default::default();
+        let unit = &mut unit[..S::WRITE_SIZE];
+        let mut unit_off = Self::unit_offset(off)?;
+        for contents in data.chunks(Self::DATA_SIZE) {
+            if contents.len() != Self::DATA_SIZE {
+                return Err(Error::Invalid);
+            }
+            unit[..Self::DATA_SIZE].copy_from_slice(contents);
+            unit[Self::DATA_SIZE..].copy_from_slice(&crc::crc32(contents).to_le_bytes());
+            self.storage.write(unit_off, unit)?;
+            unit_off += S::WRITE_SIZE;
+        }
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        if off % Self::BLOCK_SIZE != 0 || len % Self::BLOCK_SIZE != 0 {
+            return Err(Error::Invalid);
+        }
+        let blocks = len / Self::BLOCK_SIZE;
+        self.storage.erase(off / Self::BLOCK_SIZE * S::BLOCK_SIZE, blocks * S::BLOCK_SIZE)?;
+        Ok(len)
+    }
+}
@@ -0,0 +1,103 @@
+//! Simulating power loss in the middle of an operation.
+
+use crate::{
+    driver::Storage,
+    io::{Error, Result},
+};
+
+/// A storage that refuses writes and erases after an injected fault.
+///
+/// Once the configured `write` or `erase` fails with `Error::Io`, all further writes
+/// and erases fail as well, as if the device had lost power, until the fault is cleared.
+/// The failing operation leaves the underlying storage untouched.
+/// Mounting the underlying storage again should then yield a consistent filesystem:
+///
+/// ```ignore
+/// let mut faulty = FaultyStorage::new(&mut storage);
+/// faulty.fail_nth_write(3);
+/// Filesystem::mount_and_then(&mut faulty, |fs| fs.write(path, contents)).ok();
+/// Filesystem::mount_and_then(faulty.into_inner(), |fs| ...)?;
+/// ```
+pub struct FaultyStorage<'s, S: Storage> {
+    storage: &'s mut S,
+    writes_left: Option<usize>,
+    erases_left: Option<usize>,
+    tripped: bool,
+}
+
+impl<'s, S: Storage> FaultyStorage<'s, S> {
+    pub fn new(storage: &'s mut S) -> Self {
+        Self { storage, writes_left: None, erases_left: None, tripped: false }
+    }
+
+    /// Fail the `n`-th `write` from now on, counting from 1.
+    pub fn fail_nth_write(&mut self, n: usize) {
+        self.writes_left = Some(n);
+    }
+
+    /// Fail the `n`-th `erase` from now on, counting from 1.
+    pub fn fail_nth_erase(&mut self, n: usize) {
+        self.erases_left = Some(n);
+    }
+
+    /// Whether an injected fault has occurred.
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// Let writes and erases through again, and forget pending faults.
+    pub fn clear_fault(&mut self) {
+        self.writes_left = None;
+        self.erases_left = None;
+        self.tripped = false;
+    }
+
+    /// Release the underlying storage.
+    pub fn into_inner(self) -> &'s mut S {
+        self.storage
+    }
+
+    fn check(tripped: &mut bool, left: &mut Option<usize>) -> Result<()> {
+        if let Some(n) = *left {
+            if n <= 1 {
+                *left = None;
+                *tripped = true;
+            } else {
+                *left = Some(n - 1);
+            }
+        }
+        if *tripped {
+            return Err(Error::Io);
+        }
+        Ok(())
+    }
+}
+
+impl<S: Storage> Storage for FaultyStorage<'_, S> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const MAX_OPEN_FILES: usize = S::MAX_OPEN_FILES;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEADWORDS_SIZE = S::LOOKAHEADWORDS_SIZE;
+
+    fn block_count(&self) -> usize {
+        self.storage.block_count()
+    }
+
+    fn read(&self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.storage.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        Self::check(&mut self.tripped, &mut self.writes_left)?;
+        self.storage.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        Self::check(&mut self.tripped, &mut self.erases_left)?;
+        self.storage.erase(off, len)
+    }
+}
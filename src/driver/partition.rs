@@ -0,0 +1,73 @@
+//! Splitting one storage into several independent ones.
+
+use crate::{
+    driver::Storage,
+    io::{Error, Result},
+};
+
+/// A contiguous range of blocks of another storage, usable as a storage of its own.
+///
+/// The partition starts at block `FIRST_BLOCK` of the underlying storage and spans
+/// `BLOCKS` blocks. Accesses outside of it are refused with `Error::Invalid`, so
+/// formatting or writing one partition leaves the others untouched:
+///
+/// ```ignore
+/// // first half of the device, the second half is left alone
+/// let mut config = PartitionStorage::<_, 0, 256>::new(&mut storage);
+/// Filesystem::format(&mut config)?;
+/// ```
+pub struct PartitionStorage<'s, S: Storage, const FIRST_BLOCK: usize, const BLOCKS: usize> {
+    storage: &'s mut S,
+}
+
+impl<'s, S: Storage, const FIRST_BLOCK: usize, const BLOCKS: usize> PartitionStorage<'s, S, FIRST_BLOCK, BLOCKS> {
+    const GEOMETRY: () = {
+        assert!(BLOCKS > 0, "partition must not be empty");
+        assert!(FIRST_BLOCK + BLOCKS <= S::BLOCK_COUNT, "partition does not fit into the storage");
+        crate::fs::assert_storage_requirements::<Self>();
+    };
+
+    /// Fails to compile if the partition does not fit into `S::BLOCK_COUNT` blocks, and
+    /// panics if it does not fit into `storage`, which may be sized at runtime.
+    pub fn new(storage: &'s mut S) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::GEOMETRY;
+        assert!(FIRST_BLOCK + BLOCKS <= storage.block_count(), "partition does not fit into the storage");
+        Self { storage }
+    }
+
+    /// Release the underlying storage.
+    pub fn into_inner(self) -> &'s mut S {
+        self.storage
+    }
+
+    fn translate(off: usize, len: usize) -> Result<usize> {
+        if off + len > BLOCKS * S::BLOCK_SIZE {
+            return Err(Error::Invalid);
+        }
+        Ok(FIRST_BLOCK * S::BLOCK_SIZE + off)
+    }
+}
+
+impl<S: Storage, const FIRST_BLOCK: usize, const BLOCKS: usize> Storage for PartitionStorage<'_, S, FIRST_BLOCK, BLOCKS> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = BLOCKS;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const MAX_OPEN_FILES: usize = S::MAX_OPEN_FILES;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEADWORDS_SIZE = S::LOOKAHEADWORDS_SIZE;
+
+    fn read(&self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.storage.read(Self::translate(off, buf.len())?, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        self.storage.write(Self::translate(off, data.len())?, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.storage.erase(Self::translate(off, len)?, len)
+    }
+}
@@ -0,0 +1,243 @@
+//! Replacing bad blocks with spares.
+
+use core::convert::TryInto;
+
+use generic_array::typenum::Unsigned;
+
+use crate::{
+    crc,
+    driver::Storage,
+    fs::Bytes,
+    io::{Error, Result},
+};
+
+/// Marks a block of the underlying storage as holding a remap table.
+const TABLE_MAGIC: &[u8; 4] = b"RMAP";
+
+/// Marks an unused spare in a stored remap table.
+const UNUSED: u32 = u32::MAX;
+
+/// A storage that moves blocks failing to erase or write to spare blocks of another storage.
+///
+/// The last two blocks of the underlying storage hold the remap table, the `SPARES` blocks
+/// before them are spares, and the remaining blocks are passed through. When an `erase` or
+/// `write` of a block fails with `Error::Io`, the data written to the block since its last
+/// erase is copied to the next unused spare, which replaces the block from then on, and
+/// the operation is retried there. Once all spares are used up, such failures are reported
+/// as `Error::Corruption`, so littlefs falls back to its own bad block handling:
+///
+/// ```ignore
+/// let mut remap = RemapStorage::<_, 4>::new(&mut storage)?;
+/// Filesystem::format(&mut remap)?;
+/// ```
+///
+/// Copying assumes that blocks are written in order, as littlefs does. The remap table
+/// is kept in two copies with a sequence number, each change overwrites the older copy,
+/// so power loss while storing it falls back to the previous table.
+pub struct RemapStorage<'s, S: Storage, const SPARES: usize> {
+    storage: &'s mut S,
+    /// Block replaced by each spare, in order of use. Spares that failed themselves
+    /// keep their entry, later entries take precedence.
+    remap: [Option<usize>; SPARES],
+    /// Sequence number of the newest table copy, zero if there is none.
+    sequence: u32,
+    /// Which of the table blocks holds the newest copy.
+    newest: usize,
+}
+
+impl<'s, S: Storage, const SPARES: usize> RemapStorage<'s, S, SPARES> {
+    const TABLE_BLOCKS: [usize; 2] = [S::BLOCK_COUNT - 2, S::BLOCK_COUNT - 1];
+    const FIRST_SPARE: usize = S::BLOCK_COUNT - 2 - SPARES;
+    /// Magic, sequence number, one entry per spare, and a CRC-32 of all of them.
+    const TABLE_SIZE: usize = TABLE_MAGIC.len() + 4 + 4 * SPARES + 4;
+
+    /// Load the newest valid remap table of `storage`, starting with an empty one if
+    /// there is none.
+    ///
+    /// Panics if no blocks are left for data, the table does not fit into the
+    /// cache of `storage`, or `storage` is sized at runtime, as the spares and the
+    /// table are placed at its last blocks.
+    pub fn new(storage: &'s mut S) -> Result<Self> {
+        assert_eq!(storage.block_count(), S::BLOCK_COUNT, "storage must be sized at compile time");
+        assert!(SPARES + 2 < S::BLOCK_COUNT);
+        assert!(Self::TABLE_SIZE <= S::CACHE_SIZE::USIZE);
+
+        // without a valid copy, the first table goes to the first block
+        let mut remap = Self { storage, remap: [None; SPARES], sequence: 0, newest: 1 };
+        let mut table: Bytes<S::CACHE_SIZE> = Default::default();
+        let len = round_up(Self::TABLE_SIZE, S::READ_SIZE);
+        for (copy, block) in Self::TABLE_BLOCKS.iter().enumerate() {
+            remap.storage.read(block * S::BLOCK_SIZE, &mut table[..len])?;
+            let (contents, checksum) = table[..Self::TABLE_SIZE].split_at(Self::TABLE_SIZE - 4);
+            if !contents.starts_with(TABLE_MAGIC) || crc::crc32(contents).to_le_bytes() != checksum {
+                continue;
+            }
+            let (sequence, entries) = contents[TABLE_MAGIC.len()..].split_at(4);
+            let sequence = u32::from_le_bytes(sequence.try_into().unwrap());
+            if sequence <= remap.sequence {
+                continue;
+            }
+            remap.sequence = sequence;
+            remap.newest = copy;
+            for (spare, entry) in remap.remap.iter_mut().zip(entries.chunks(4)) {
+                let block = u32::from_le_bytes(entry.try_into().unwrap());
+                *spare = if block != UNUSED { Some(block as usize) } else { None };
+            }
+        }
+        Ok(remap)
+    }
+
+    /// Number of spares in use, including spares that failed themselves.
+    pub fn remapped_blocks(&self) -> usize {
+        self.remap.iter().filter(|spare| spare.is_some()).count()
+    }
+
+    /// Release the underlying storage.
+    pub fn into_inner(self) -> &'s mut S {
+        self.storage
+    }
+
+    /// Block of the underlying storage currently holding `block`.
+    fn physical_block(&self, block: usize) -> usize {
+        match self.remap.iter().rposition(|&spare| spare == Some(block)) {
+            Some(spare) => Self::FIRST_SPARE + spare,
+            None => block,
+        }
+    }
+
+    /// Move `block` to an unused spare, keeping the first `keep` bytes of its data.
+    ///
+    /// The table only changes if the new one is stored.
+    fn replace(&mut self, block: usize, keep: usize) -> Result<()> {
+        let old = self.physical_block(block);
+        let mut remap = self.remap;
+        loop {
+            let spare = remap.iter().position(Option::is_none).ok_or(Error::Corruption)?;
+            remap[spare] = Some(block);
+            match self.copy(old, Self::FIRST_SPARE + spare, keep) {
+                // try the next spare
+                Err(Error::Io) => continue,
+                result => result?,
+            }
+            let previous = core::mem::replace(&mut self.remap, remap);
+            let result = self.store_table();
+            if result.is_err() {
+                self.remap = previous;
+            }
+            return result;
+        }
+    }
+
+    fn copy(&mut self, from: usize, to: usize, len: usize) -> Result<()> {
+        self.storage.erase(to * S::BLOCK_SIZE, S::BLOCK_SIZE)?;
+        let mut buf: Bytes<S::CACHE_SIZE> = Default::default();
+        let mut off = 0;
+        while off < len {
+            // cache size is a multiple of read and write size, and divides the block size
+            let chunk = core::cmp::min(buf.len(), len - off);
+            self.storage.read(from * S::BLOCK_SIZE + off, &mut buf[..round_up(chunk, S::READ_SIZE)])?;
+            self.storage.write(to * S::BLOCK_SIZE + off, &buf[..chunk])?;
+            off += chunk;
+        }
+        Ok(())
+    }
+
+    fn store_table(&mut self) -> Result<()> {
+        let mut table: Bytes<S::CACHE_SIZE> = Default::default();
+        let len = round_up(Self::TABLE_SIZE, S::WRITE_SIZE);
+        for byte in table[Self::TABLE_SIZE..len].iter_mut() {
+            *byte = 0xff;
+        }
+        let sequence = self.sequence + 1;
+        table[..TABLE_MAGIC.len()].copy_from_slice(TABLE_MAGIC);
+        table[TABLE_MAGIC.len()..][..4].copy_from_slice(&sequence.to_le_bytes());
+        let entries = table[TABLE_MAGIC.len() + 4..Self::TABLE_SIZE - 4].chunks_mut(4);
+        for (entry, spare) in entries.zip(self.remap.iter()) {
+            let block = spare.map(|block| block as u32).unwrap_or(UNUSED);
+            entry.copy_from_slice(&block.to_le_bytes());
+        }
+        let checksum = crc::crc32(&table[..Self::TABLE_SIZE - 4]);
+        table[Self::TABLE_SIZE - 4..Self::TABLE_SIZE].copy_from_slice(&checksum.to_le_bytes());
+
+        // keep the newest copy until the other one is complete
+        let copy = 1 - self.newest;
+        let block = Self::TABLE_BLOCKS[copy];
+        self.storage.erase(block * S::BLOCK_SIZE, S::BLOCK_SIZE)?;
+        self.storage.write(block * S::BLOCK_SIZE, &table[..len])?;
+        self.sequence = sequence;
+        self.newest = copy;
+        Ok(())
+    }
+}
+
+fn round_up(len: usize, unit: usize) -> usize {
+    match len % unit {
+        0 => len,
+        rest => len + unit - rest,
+    }
+}
+
+impl<S: Storage, const SPARES: usize> Storage for RemapStorage<'_, S, SPARES> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT - 2 - SPARES;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const MAX_OPEN_FILES: usize = S::MAX_OPEN_FILES;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEADWORDS_SIZE = S::LOOKAHEADWORDS_SIZE;
+
+    fn read(&self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        if off + buf.len() > Self::BLOCK_COUNT * Self::BLOCK_SIZE {
+            return Err(Error::Invalid);
+        }
+        let mut done = 0;
+        while done < buf.len() {
+            let (block, in_block) = ((off + done) / Self::BLOCK_SIZE, (off + done) % Self::BLOCK_SIZE);
+            let len = core::cmp::min(buf.len() - done, Self::BLOCK_SIZE - in_block);
+            let physical = self.physical_block(block) * Self::BLOCK_SIZE + in_block;
+            self.storage.read(physical, &mut buf[done..done + len])?;
+            done += len;
+        }
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        if off + data.len() > Self::BLOCK_COUNT * Self::BLOCK_SIZE {
+            return Err(Error::Invalid);
+        }
+        let mut done = 0;
+        while done < data.len() {
+            let (block, in_block) = ((off + done) / Self::BLOCK_SIZE, (off + done) % Self::BLOCK_SIZE);
+            let len = core::cmp::min(data.len() - done, Self::BLOCK_SIZE - in_block);
+            loop {
+                let physical = self.physical_block(block) * Self::BLOCK_SIZE + in_block;
+                match self.storage.write(physical, &data[done..done + len]) {
+                    Err(Error::Io) => self.replace(block, in_block)?,
+                    result => { result?; break; }
+                }
+            }
+            done += len;
+        }
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        if off % Self::BLOCK_SIZE != 0 || len % Self::BLOCK_SIZE != 0
+            || off + len > Self::BLOCK_COUNT * Self::BLOCK_SIZE
+        {
+            return Err(Error::Invalid);
+        }
+        for block in off / Self::BLOCK_SIZE..(off + len) / Self::BLOCK_SIZE {
+            loop {
+                let physical = self.physical_block(block) * Self::BLOCK_SIZE;
+                match self.storage.erase(physical, Self::BLOCK_SIZE) {
+                    Err(Error::Io) => self.replace(block, 0)?,
+                    result => { result?; break; }
+                }
+            }
+        }
+        Ok(len)
+    }
+}
+
@@ -0,0 +1,92 @@
+//! Storing a filesystem in a borrowed buffer.
+
+use core::marker::PhantomData;
+
+use generic_array::ArrayLength;
+
+use crate::{
+    driver::Storage,
+    io::{Error, Result},
+};
+
+/// A storage backed by a borrowed byte array, such as a `static mut` buffer.
+///
+/// The array of `N` bytes is split into blocks of `BLOCK_SIZE` bytes, which must divide
+/// `N`. Reads and writes can be of any size, erasing fills blocks with `0xff`. The cache
+/// size `C` and the lookahead size `L` are given as for other storages:
+///
+/// ```ignore
+/// let mut buf = [0xff; 8192];
+/// let mut storage = SliceStorage::<8192, 512, consts::U32, consts::U1>::new(&mut buf);
+/// Filesystem::format(&mut storage)?;
+/// ```
+pub struct SliceStorage<'a, const N: usize, const BLOCK_SIZE: usize, C, L> {
+    buf: &'a mut [u8; N],
+    geometry: PhantomData<(C, L)>,
+}
+
+impl<'a, const N: usize, const BLOCK_SIZE: usize, C, L> SliceStorage<'a, N, BLOCK_SIZE, C, L>
+where
+    C: ArrayLength<u8>,
+    L: ArrayLength<u32>,
+{
+    const ERASE_VALUE: u8 = 0xff;
+    const GEOMETRY: () = {
+        assert!(BLOCK_SIZE > 0 && N % BLOCK_SIZE == 0, "buffer must hold whole blocks");
+        crate::fs::assert_storage_requirements::<Self>();
+    };
+
+    /// Fails to compile if `buf` cannot hold a filesystem with the given geometry.
+    ///
+    /// The contents of `buf` are kept, so a previously formatted buffer can be mounted.
+    pub fn new(buf: &'a mut [u8; N]) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::GEOMETRY;
+        Self { buf, geometry: PhantomData }
+    }
+
+    /// Release the underlying buffer.
+    pub fn into_inner(self) -> &'a mut [u8; N] {
+        self.buf
+    }
+
+    fn range(off: usize, len: usize) -> Result<core::ops::Range<usize>> {
+        if off + len > N {
+            return Err(Error::Invalid);
+        }
+        Ok(off..off + len)
+    }
+}
+
+impl<const N: usize, const BLOCK_SIZE: usize, C, L> Storage for SliceStorage<'_, N, BLOCK_SIZE, C, L>
+where
+    C: ArrayLength<u8>,
+    L: ArrayLength<u32>,
+{
+    const READ_SIZE: usize = 1;
+    const WRITE_SIZE: usize = 1;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const BLOCK_COUNT: usize = N / BLOCK_SIZE;
+    type CACHE_SIZE = C;
+    type LOOKAHEADWORDS_SIZE = L;
+
+    fn read(&self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        buf.copy_from_slice(&self.buf[Self::range(off, buf.len())?]);
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        self.buf[Self::range(off, data.len())?].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        if off % BLOCK_SIZE != 0 || len % BLOCK_SIZE != 0 {
+            return Err(Error::Invalid);
+        }
+        for byte in self.buf[Self::range(off, len)?].iter_mut() {
+            *byte = Self::ERASE_VALUE;
+        }
+        Ok(len)
+    }
+}
@@ -0,0 +1,91 @@
+//! Simulating the access times of real flash.
+
+use core::cell::Cell;
+use std::{thread, time::Duration};
+
+use crate::{
+    driver::Storage,
+    io::Result,
+};
+
+/// A storage that takes a fixed time for every read, write and erase of another storage.
+///
+/// Each operation sleeps for its configured latency before it is passed on, and the
+/// latencies of all operations add up to the simulated flash time, so two access
+/// patterns can be compared by their total time on the device:
+///
+/// ```ignore
+/// let mut slow = SlowStorage::new(&mut storage, read, write, erase);
+/// Filesystem::mount_and_then(&mut slow, |fs| fs.write(path, contents))?;
+/// println!("{:?}", slow.elapsed());
+/// ```
+///
+/// The latencies are per call, not per byte or block.
+pub struct SlowStorage<'s, S: Storage> {
+    storage: &'s mut S,
+    read_latency: Duration,
+    write_latency: Duration,
+    erase_latency: Duration,
+    elapsed: Cell<Duration>,
+}
+
+impl<'s, S: Storage> SlowStorage<'s, S> {
+    pub fn new(
+        storage: &'s mut S,
+        read_latency: Duration,
+        write_latency: Duration,
+        erase_latency: Duration,
+    ) -> Self {
+        Self { storage, read_latency, write_latency, erase_latency, elapsed: Cell::new(Duration::ZERO) }
+    }
+
+    /// Simulated flash time of all operations so far.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.get()
+    }
+
+    /// Start counting the simulated flash time from zero.
+    pub fn reset_elapsed(&mut self) {
+        self.elapsed.set(Duration::ZERO);
+    }
+
+    /// Release the underlying storage.
+    pub fn into_inner(self) -> &'s mut S {
+        self.storage
+    }
+
+    fn wait(&self, latency: Duration) {
+        thread::sleep(latency);
+        self.elapsed.set(self.elapsed.get() + latency);
+    }
+}
+
+impl<S: Storage> Storage for SlowStorage<'_, S> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const MAX_OPEN_FILES: usize = S::MAX_OPEN_FILES;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEADWORDS_SIZE = S::LOOKAHEADWORDS_SIZE;
+
+    fn block_count(&self) -> usize {
+        self.storage.block_count()
+    }
+
+    fn read(&self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.wait(self.read_latency);
+        self.storage.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        self.wait(self.write_latency);
+        self.storage.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.wait(self.erase_latency);
+        self.storage.erase(off, len)
+    }
+}
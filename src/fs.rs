@@ -1,16 +1,26 @@
 //! Experimental Filesystem version using closures.
 
-use core::{cell::RefCell, cmp, mem, slice};
+use core::{cell::{Cell, RefCell}, cmp, mem, ptr, slice};
 
 use bitflags::bitflags;
 use generic_array::typenum::marker_traits::Unsigned;
 use littlefs2_sys as ll;
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 // so far, don't need `heapless-bytes`.
 pub type Bytes<SIZE> = generic_array::GenericArray<u8, SIZE>;
 
+/// Start of archives written by `Filesystem::export_tar`, including the format version.
+const ARCHIVE_MAGIC: &[u8; 4] = b"LFA1";
+/// Tags of the records in an archive.
+const ARCHIVE_DIR: u8 = b'd';
+const ARCHIVE_FILE: u8 = b'f';
+const ARCHIVE_ATTRIBUTE: u8 = b'a';
+const ARCHIVE_END: u8 = b'e';
+
 use crate::{
+    crc,
     io::{self, Result},
     path::{Path, PathBuf},
     driver,
@@ -20,7 +30,8 @@ struct Cache<Storage: driver::Storage> {
     read: Bytes<Storage::CACHE_SIZE>,
     write: Bytes<Storage::CACHE_SIZE>,
     // lookahead: aligned::Aligned<aligned::A4, Bytes<Storage::LOOKAHEAD_SIZE>>,
-    lookahead: generic_array::GenericArray<u32, Storage::LOOKAHEADWORDS_SIZE>,
+    // `32 * LOOKAHEADWORDS_SIZE` bytes, the lookahead size littlefs is configured with
+    lookahead: [generic_array::GenericArray<u32, Storage::LOOKAHEADWORDS_SIZE>; 8],
 }
 
 impl<S: driver::Storage> Cache<S> {
@@ -46,7 +57,160 @@ pub struct Allocation<Storage: driver::Storage> {
     state: ll::lfs_t,
 }
 
-// pub fn check_storage_requirements(
+/// A `driver::Storage` geometry invariant that does not hold.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// `BLOCK_CYCLES` is neither positive nor -1.
+    InvalidBlockCycles,
+    /// `BLOCK_COUNT` is zero.
+    ZeroBlockCount,
+    /// `READ_SIZE` is zero.
+    ZeroReadSize,
+    /// `WRITE_SIZE` is zero.
+    ZeroWriteSize,
+    /// `BLOCK_SIZE` is less than 128.
+    BlockTooSmall,
+    /// `CACHE_SIZE` is zero.
+    ZeroCacheSize,
+    /// `LOOKAHEADWORDS_SIZE` is zero.
+    ZeroLookahead,
+    /// `CACHE_SIZE` is not a multiple of `READ_SIZE`.
+    CacheNotMultipleOfRead,
+    /// `CACHE_SIZE` is not a multiple of `WRITE_SIZE`.
+    CacheNotMultipleOfWrite,
+    /// `BLOCK_SIZE` is not a multiple of `CACHE_SIZE`.
+    BlockNotMultipleOfCache,
+    /// `consts::FILENAME_MAX_PLUS_ONE` is not supported by the bindings.
+    InvalidNameMax,
+    /// `consts::FILEBYTES_MAX` is not supported by the bindings.
+    InvalidFileMax,
+    /// `consts::ATTRBYTES_MAX` is not supported by the bindings.
+    InvalidAttrMax,
+}
+
+impl ConfigError {
+    /// A short description of the violated invariant.
+    pub const fn message(self) -> &'static str {
+        match self {
+            ConfigError::InvalidBlockCycles => "BLOCK_CYCLES must be positive or -1",
+            ConfigError::ZeroBlockCount => "BLOCK_COUNT must not be zero",
+            ConfigError::ZeroReadSize => "READ_SIZE must not be zero",
+            ConfigError::ZeroWriteSize => "WRITE_SIZE must not be zero",
+            ConfigError::BlockTooSmall => "BLOCK_SIZE must be at least 128",
+            ConfigError::ZeroCacheSize => "CACHE_SIZE must not be zero",
+            ConfigError::ZeroLookahead => "LOOKAHEADWORDS_SIZE must not be zero",
+            ConfigError::CacheNotMultipleOfRead => "CACHE_SIZE must be a multiple of READ_SIZE",
+            ConfigError::CacheNotMultipleOfWrite => "CACHE_SIZE must be a multiple of WRITE_SIZE",
+            ConfigError::BlockNotMultipleOfCache => "BLOCK_SIZE must be a multiple of CACHE_SIZE",
+            ConfigError::InvalidNameMax => "FILENAME_MAX_PLUS_ONE must be 256",
+            ConfigError::InvalidFileMax => "FILEBYTES_MAX must be 2_147_483_647",
+            ConfigError::InvalidAttrMax => "ATTRBYTES_MAX must be 1_022",
+        }
+    }
+}
+
+/// Check the geometry of `Storage` against the requirements of littlefs.
+///
+/// `Allocation::new` only checks these with debug assertions, use
+/// `Allocation::try_new` (or `Filesystem::try_format`) to get an error instead,
+/// or `assert_storage_requirements` to reject the geometry at compile time.
+pub const fn check_storage_requirements<Storage: driver::Storage>() -> core::result::Result<(), ConfigError> {
+    let read_size = Storage::READ_SIZE;
+    let write_size = Storage::WRITE_SIZE;
+    let block_size = Storage::BLOCK_SIZE;
+    let cache_size = <Storage as driver::Storage>::CACHE_SIZE::USIZE;
+    let lookahead_words = <Storage as driver::Storage>::LOOKAHEADWORDS_SIZE::USIZE;
+    let block_cycles = Storage::BLOCK_CYCLES;
+
+    if block_cycles == 0 || block_cycles < -1 {
+        return Err(ConfigError::InvalidBlockCycles);
+    }
+    if Storage::BLOCK_COUNT == 0 {
+        return Err(ConfigError::ZeroBlockCount);
+    }
+    if read_size == 0 {
+        return Err(ConfigError::ZeroReadSize);
+    }
+    if write_size == 0 {
+        return Err(ConfigError::ZeroWriteSize);
+    }
+    // https://github.com/ARMmbed/littlefs/issues/264
+    if block_size < 128 {
+        return Err(ConfigError::BlockTooSmall);
+    }
+    if cache_size == 0 {
+        return Err(ConfigError::ZeroCacheSize);
+    }
+    if lookahead_words == 0 {
+        return Err(ConfigError::ZeroLookahead);
+    }
+    if cache_size % read_size != 0 {
+        return Err(ConfigError::CacheNotMultipleOfRead);
+    }
+    if cache_size % write_size != 0 {
+        return Err(ConfigError::CacheNotMultipleOfWrite);
+    }
+    if block_size % cache_size != 0 {
+        return Err(ConfigError::BlockNotMultipleOfCache);
+    }
+    Ok(())
+}
+
+/// Check the name, file and attribute size limits against what the bindings support.
+///
+/// The limits are crate constants, `Allocation::try_new` checks them before use.
+pub(crate) const fn check_limits(
+    filename_max_plus_one: u32,
+    file_max: u32,
+    attr_max: u32,
+) -> core::result::Result<(), ConfigError> {
+    // limitation of ll-bindings, within littlefs' range of 1 to 1_022
+    if filename_max_plus_one != 255 + 1 {
+        return Err(ConfigError::InvalidNameMax);
+    }
+    // limitation of ll-bindings, littlefs' maximum
+    if file_max != 2_147_483_647 {
+        return Err(ConfigError::InvalidFileMax);
+    }
+    // limitation of ll-bindings, littlefs' maximum
+    if attr_max != 1_022 {
+        return Err(ConfigError::InvalidAttrMax);
+    }
+    Ok(())
+}
+
+/// Panic if the geometry of `Storage` does not meet the requirements of littlefs.
+///
+/// Evaluated in a constant, this turns a misconfigured storage into a compile error:
+/// storages defined with `vec_ram_storage!` and `PartitionStorage` do so on construction,
+/// for other storages add the check next to the `Storage` implementation.
+///
+/// ```compile_fail
+/// # use littlefs2::{consts, driver::Storage, fs::assert_storage_requirements, io::Result};
+/// struct Flash;
+///
+/// impl Storage for Flash {
+///     const READ_SIZE: usize = 16;
+///     const WRITE_SIZE: usize = 16;
+///     const BLOCK_SIZE: usize = 512;
+///     const BLOCK_COUNT: usize = 8;
+///     // not a multiple of READ_SIZE
+///     type CACHE_SIZE = consts::U24;
+///     type LOOKAHEADWORDS_SIZE = consts::U1;
+///
+///     fn read(&self, _: usize, _: &mut [u8]) -> Result<usize> { unimplemented!() }
+///     fn write(&mut self, _: usize, _: &[u8]) -> Result<usize> { unimplemented!() }
+///     fn erase(&mut self, _: usize, _: usize) -> Result<usize> { unimplemented!() }
+/// }
+///
+/// const _: () = assert_storage_requirements::<Flash>();
+/// ```
+pub const fn assert_storage_requirements<Storage: driver::Storage>() {
+    if let Err(error) = check_storage_requirements::<Storage>() {
+        panic!("{}", error.message());
+    }
+}
 
 impl<Storage: driver::Storage> Default for Allocation<Storage> {
     fn default() -> Self {
@@ -55,6 +219,18 @@ impl<Storage: driver::Storage> Default for Allocation<Storage> {
 }
 impl<Storage: driver::Storage> Allocation<Storage> {
 
+    /// Like `new`, but returns `InvalidConfig` instead of asserting on the geometry
+    /// and the size limits.
+    pub fn try_new() -> Result<Allocation<Storage>> {
+        check_storage_requirements::<Storage>()?;
+        check_limits(
+            crate::consts::FILENAME_MAX_PLUS_ONE,
+            crate::consts::FILEBYTES_MAX,
+            crate::consts::ATTRBYTES_MAX,
+        )?;
+        Ok(Self::new())
+    }
+
     pub fn new() -> Allocation<Storage> {
         let read_size: u32 = Storage::READ_SIZE as _;
         let write_size: u32 = Storage::WRITE_SIZE as _;
@@ -92,23 +268,13 @@ impl<Storage: driver::Storage> Allocation<Storage> {
         let cache = Cache::new();
 
         let filename_max_plus_one: u32 = crate::consts::FILENAME_MAX_PLUS_ONE;
-        debug_assert!(filename_max_plus_one > 1);
-        debug_assert!(filename_max_plus_one <= 1_022+1);
-        // limitation of ll-bindings
-        debug_assert!(filename_max_plus_one == 255+1);
         let path_max_plus_one: u32 = crate::consts::PATH_MAX_PLUS_ONE as _;
         // TODO: any upper limit?
         debug_assert!(path_max_plus_one >= filename_max_plus_one);
         let file_max = crate::consts::FILEBYTES_MAX;
-        assert!(file_max > 0);
-        assert!(file_max <= 2_147_483_647);
-        // limitation of ll-bindings
-        assert!(file_max == 2_147_483_647);
         let attr_max: u32 = crate::consts::ATTRBYTES_MAX;
-        assert!(attr_max > 0);
-        assert!(attr_max <= 1_022);
-        // limitation of ll-bindings
-        assert!(attr_max == 1_022);
+        // only checked in debug builds, like the geometry; `try_new` returns an error instead
+        debug_assert_eq!(check_limits(filename_max_plus_one, file_max, attr_max), Ok(()));
 
         let config = ll::lfs_config {
             context: core::ptr::null_mut(),
@@ -156,10 +322,16 @@ impl<Storage: driver::Storage> Allocation<Storage> {
 pub struct Filesystem<'a, Storage: driver::Storage> {
     alloc: RefCell<&'a mut Allocation<Storage>>,
     storage: &'a mut Storage,
+    // files opened through `OpenOptions::open` and not closed yet
+    open_files: Cell<usize>,
+    // the open files with write access, linked through `FileAllocation::next_writer`
+    writers: Cell<*mut FileAllocation<Storage>>,
 }
 
 /// Regular file vs directory
-#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq,Serialize,Deserialize)]
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum FileType {
     File,
     Dir,
@@ -178,9 +350,12 @@ impl FileType {
 }
 
 /// File type (regular vs directory) and size of a file.
-#[derive(Clone,Debug,Eq,PartialEq,Serialize,Deserialize)]
+#[derive(Clone,Debug,Eq,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Metadata {
     file_type: FileType,
+    #[cfg_attr(feature = "serde", serde(rename = "len"))]
     size: usize,
 }
 
@@ -223,7 +398,96 @@ impl From<ll::lfs_info> for Metadata
     }
 }
 
-impl<Storage: driver::Storage> Filesystem<'_, Storage> {
+/// Options for [`Filesystem::format_with_options`](struct.Filesystem.html#method.format_with_options).
+///
+/// The bundled littlefs writes only one on-disk version (see `disk_version`), so
+/// there is no option to select another one.
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+pub struct FormatOptions {
+    block_cycles: Option<isize>,
+    erase_value: Option<u8>,
+}
+
+impl FormatOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `block_cycles` instead of `Storage::BLOCK_CYCLES` while formatting.
+    ///
+    /// Must be positive, or -1 to disable wear-leveling. This only affects the format
+    /// itself, later mounts use `Storage::BLOCK_CYCLES` again.
+    pub fn block_cycles(mut self, block_cycles: isize) -> Self {
+        self.block_cycles = Some(block_cycles);
+        self
+    }
+
+    /// Before formatting, erase the first block and check that it reads back as
+    /// `erase_value`, failing with `Io` otherwise.
+    ///
+    /// This catches drivers whose `erase` does not leave the value the flash part is
+    /// documented to, early on and before any data is stored.
+    pub fn verify_erase(mut self, erase_value: u8) -> Self {
+        self.erase_value = Some(erase_value);
+        self
+    }
+}
+
+/// Options for [`Filesystem::mount_with_options`](struct.Filesystem.html#method.mount_with_options).
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+pub struct MountOptions {
+    block_cycles: Option<isize>,
+    lookahead_size: Option<usize>,
+}
+
+impl MountOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `block_cycles` instead of `Storage::BLOCK_CYCLES` while mounted.
+    ///
+    /// Must be positive, or -1 to disable wear-leveling. Nothing about it is stored,
+    /// so mounts may differ in this setting.
+    pub fn block_cycles(mut self, block_cycles: isize) -> Self {
+        self.block_cycles = Some(block_cycles);
+        self
+    }
+
+    /// Use a lookahead of `lookahead_size` bytes instead of the whole lookahead buffer.
+    ///
+    /// Must be a nonzero multiple of 8, and at most `32 * Storage::LOOKAHEADWORDS_SIZE`,
+    /// the size of the buffer and the default. Each byte covers eight blocks, a larger
+    /// lookahead finds free blocks on a fuller filesystem with fewer scans.
+    pub fn lookahead_size(mut self, lookahead_size: usize) -> Self {
+        self.lookahead_size = Some(lookahead_size);
+        self
+    }
+
+    fn apply<Storage: driver::Storage>(&self, config: &mut ll::lfs_config) -> Result<()> {
+        if let Some(block_cycles) = self.block_cycles {
+            check_block_cycles(block_cycles)?;
+            config.block_cycles = block_cycles as _;
+        }
+        if let Some(lookahead_size) = self.lookahead_size {
+            let max = 32 * <Storage as driver::Storage>::LOOKAHEADWORDS_SIZE::USIZE;
+            if lookahead_size == 0 || lookahead_size % 8 != 0 || lookahead_size > max {
+                return Err(io::Error::Invalid);
+            }
+            config.lookahead_size = lookahead_size as _;
+        }
+        Ok(())
+    }
+}
+
+fn check_block_cycles(block_cycles: isize) -> Result<()> {
+    if block_cycles == 0 || block_cycles < -1 {
+        return Err(io::Error::Invalid);
+    }
+    Ok(())
+}
+
+impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
 
     pub fn allocate() -> Allocation<Storage> {
         Allocation::new()
@@ -238,7 +502,67 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         io::result_from((), return_code)
     }
 
-    // TODO: check if this is equivalent to `is_formatted`.
+    /// Like `format`, but returns `InvalidConfig` instead of asserting on the geometry.
+    pub fn try_format(storage: &mut Storage) -> Result<()> {
+        check_storage_requirements::<Storage>()?;
+        Self::format(storage)
+    }
+
+    /// Like `mount_and_then`, but returns `InvalidConfig` instead of asserting on the geometry.
+    pub fn try_mount_and_then<R>(
+        storage: &mut Storage,
+        f: impl FnOnce(&Filesystem<'_, Storage>) -> Result<R>,
+    ) -> Result<R> {
+        let mut alloc = Allocation::try_new()?;
+        let fs = Filesystem::mount(&mut alloc, storage)?;
+        fs.run_and_unmount(f)
+    }
+
+    /// Like `format`, with the given options. Returns `Invalid` for invalid options.
+    pub fn format_with_options(storage: &mut Storage, options: FormatOptions) -> Result<()> {
+        let alloc = &mut Allocation::new();
+        if let Some(block_cycles) = options.block_cycles {
+            check_block_cycles(block_cycles)?;
+            alloc.config.block_cycles = block_cycles as _;
+        }
+        if let Some(erase_value) = options.erase_value {
+            Self::verify_erase(storage, erase_value)?;
+        }
+        let fs = Filesystem::new(alloc, storage);
+        let mut alloc = fs.alloc.borrow_mut();
+        let return_code = unsafe { ll::lfs_format(&mut alloc.state, &alloc.config) };
+        io::result_from((), return_code)
+    }
+
+    /// Erase the first block, which formatting overwrites anyway, and check its contents.
+    fn verify_erase(storage: &mut Storage, erase_value: u8) -> Result<()> {
+        storage.erase(0, Storage::BLOCK_SIZE)?;
+        // the cache size divides the block size and is a multiple of the read size
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+        for off in (0..Storage::BLOCK_SIZE).step_by(buf.len()) {
+            storage.read(off, &mut buf)?;
+            if buf.iter().any(|&byte| byte != erase_value) {
+                return Err(io::Error::Io);
+            }
+        }
+        Ok(())
+    }
+
+    /// The on-disk version of the mounted filesystem, major version in the upper 16 bits.
+    ///
+    /// littlefs only mounts filesystems with the same major and at most the same minor
+    /// version as it writes, and the bundled littlefs writes the oldest 2.x format,
+    /// so this is always `ll::LFS_DISK_VERSION`.
+    pub fn disk_version(&self) -> u32 {
+        ll::LFS_DISK_VERSION
+    }
+
+    // TODO: offer `migrate` for littlefs v1 images. `lfs_migrate` is only compiled with
+    // `LFS_MIGRATE` defined, which `littlefs2-sys` does not do, so it is not in `ll`.
+
+    /// Check whether `storage` holds a filesystem, e.g. to decide to format on first boot.
+    ///
+    /// Mounting only reads from the storage, so this does not modify it.
     pub fn is_mountable(storage: &mut Storage) -> bool {
         let alloc = &mut Allocation::new();
         matches!(Filesystem::mount(alloc, storage), Ok(_))
@@ -252,6 +576,9 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
     }
 
     /// This API avoids the need for using `Allocation`.
+    ///
+    /// The filesystem is unmounted after `f` returns, also if it fails; an error of `f`
+    /// takes precedence over one from unmounting.
     pub fn mount_and_then<R>(
         storage: &mut Storage,
         f: impl FnOnce(&Filesystem<'_, Storage>) -> Result<R>,
@@ -259,17 +586,151 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
 
         let mut alloc = Allocation::new();
         let fs = Filesystem::mount(&mut alloc, storage)?;
-        f(&fs)
+        fs.run_and_unmount(f)
     }
 
+    /// Like `mount_and_then`, with the given options. Returns `Invalid` for invalid options.
+    pub fn mount_and_then_with_options<R>(
+        storage: &mut Storage,
+        options: MountOptions,
+        f: impl FnOnce(&Filesystem<'_, Storage>) -> Result<R>,
+    ) -> Result<R> {
+        let mut alloc = Allocation::new();
+        let fs = Filesystem::mount_with_options(&mut alloc, storage, options)?;
+        fs.run_and_unmount(f)
+    }
+
+    fn run_and_unmount<R>(self, f: impl FnOnce(&Filesystem<'_, Storage>) -> Result<R>) -> Result<R> {
+        let result = f(&self);
+        let unmounted = self.unmount();
+        let value = result?;
+        unmounted.map(|()| value)
+    }
+
+    // TODO: wrap `lfs_fs_grow` (littlefs 2.7) as `grow`, once `littlefs2-sys` bundles a littlefs
+    // that has it. Growing also needs `Storage::block_count` to change while mounted, as
+    // the superblock stores it and littlefs only reads it on mounting.
+
     /// Total number of blocks in the filesystem
     pub fn total_blocks(&self) -> usize {
-        Storage::BLOCK_COUNT
+        self.alloc.borrow().config.block_count as usize
     }
 
     /// Total number of bytes in the filesystem
     pub fn total_space(&self) -> usize {
-        Storage::BLOCK_COUNT * Storage::BLOCK_SIZE
+        self.total_blocks() * Storage::BLOCK_SIZE
+    }
+
+    /// Read, program and block size of the mounted filesystem, in bytes.
+    pub fn io_sizes(&self) -> (usize, usize, usize) {
+        let config = &self.alloc.borrow().config;
+        (config.read_size as usize, config.prog_size as usize, config.block_size as usize)
+    }
+
+    /// The name, file and attribute size limits the filesystem is configured with.
+    pub fn limits(&self) -> Limits {
+        let config = &self.alloc.borrow().config;
+        Limits {
+            name_max: config.name_max as usize,
+            file_max: config.file_max as usize,
+            attr_max: config.attr_max as usize,
+        }
+    }
+
+    /// Number of files currently open.
+    ///
+    /// Files opened through the closure-based APIs are closed when the closure returns,
+    /// so a nonzero count outside of them points at a leaked handle.
+    pub fn open_file_count(&self) -> usize {
+        self.open_files.get()
+    }
+
+    /// Write out the pending data of all files open for writing, without closing them.
+    ///
+    /// Other operations commit their metadata before returning, and the littlefs version
+    /// of the bindings has no filesystem-wide sync, so this syncs each open writer in turn.
+    /// Files opened read-only have nothing to write out.
+    /// The config `sync` callback does nothing, as `Storage` has no sync of its own.
+    /// All files are synced even if one fails, the first error is returned.
+    pub fn sync(&self) -> Result<()> {
+        let mut result = Ok(());
+        let mut alloc = self.alloc.borrow_mut();
+        let mut writer = self.writers.get();
+        while !writer.is_null() {
+            // NOTE(unsafe) writers are unlinked when closed, before their allocation is released
+            let return_code = unsafe { ll::lfs_file_sync(&mut alloc.state, &mut (*writer).state) };
+            if result.is_ok() {
+                result = io::result_from((), return_code);
+            }
+            writer = unsafe { (*writer).next_writer.get() };
+        }
+        result
+    }
+
+    /// Whether a file open for writing is the one at `path`.
+    ///
+    /// Opens `path` read-only in `alloc` to learn its place in the metadata,
+    /// which littlefs keeps up to date for open files.
+    unsafe fn has_writer(&self, alloc: &mut FileAllocation<Storage>, path: &Path) -> bool {
+        if self.writers.get().is_null() {
+            return false;
+        }
+        // not `alloc.config`, opening would read the stored attributes into its buffers
+        let mut config: ll::lfs_file_config = mem::MaybeUninit::zeroed().assume_init();
+        config.buffer = &mut alloc.cache as *mut _ as *mut cty::c_void;
+        let mut fs_alloc = self.alloc.borrow_mut();
+        let return_code = ll::lfs_file_opencfg(
+            &mut fs_alloc.state,
+            &mut alloc.state,
+            path.as_ptr(),
+            ll::lfs_open_flags_LFS_O_RDONLY as i32,
+            &config,
+        );
+        if return_code != 0 {
+            // a missing file has no writer, other errors surface when actually opening
+            return false;
+        }
+        let mut found = false;
+        let mut writer = self.writers.get();
+        while !writer.is_null() {
+            let other = &(*writer).state;
+            if other.id == alloc.state.id && other.m.pair == alloc.state.m.pair {
+                found = true;
+                break;
+            }
+            writer = (*writer).next_writer.get();
+        }
+        // read-only files have nothing to commit
+        ll::lfs_file_close(&mut fs_alloc.state, &mut alloc.state);
+        found
+    }
+
+    /// Remove `alloc` from the open writers, if it is one of them.
+    fn unlink_writer(&self, alloc: *mut FileAllocation<Storage>) {
+        let mut link = &self.writers;
+        // NOTE(unsafe) writers are unlinked when closed, before their allocation is released
+        unsafe {
+            while !link.get().is_null() {
+                let writer = link.get();
+                if writer == alloc {
+                    link.set((*writer).next_writer.get());
+                    (*writer).next_writer.set(ptr::null_mut());
+                    return;
+                }
+                link = &(*writer).next_writer;
+            }
+        }
+    }
+
+    /// Largest file size, in bytes, that is stored inline in its directory's metadata.
+    ///
+    /// Larger files get blocks of their own. The littlefs version of the bindings derives
+    /// this from the cache and block sizes and offers no option to tune it; it is the
+    /// smallest of 1022 bytes, `CACHE_SIZE`, and an eighth of `BLOCK_SIZE`.
+    pub fn inline_max(&self) -> usize {
+        let config = &self.alloc.borrow().config;
+        let inline_max = cmp::min(config.cache_size, config.block_size / 8);
+        cmp::min(0x3fe, inline_max) as usize
     }
 
     /// Available number of unused blocks in the filesystem
@@ -294,6 +755,93 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         self.available_blocks().map(|blocks| blocks * Storage::BLOCK_SIZE)
     }
 
+    /// Call `f` with the number of each block currently in use by the filesystem.
+    ///
+    /// Traversal stops at the first error returned by `f`, which is passed on.
+    /// Blocks shared by several structures may be visited more than once.
+    ///
+    /// The filesystem is borrowed during traversal, so `f` must not use it
+    /// (doing so panics).
+    pub fn traverse<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(ll::lfs_block_t) -> Result<()>,
+    {
+        let mut context = TraverseContext { f: &mut f, error: None };
+        let return_code = unsafe { ll::lfs_fs_traverse(
+            &mut self.alloc.borrow_mut().state,
+            Some(lfs_traverse_callback::<F>),
+            &mut context as *mut _ as *mut cty::c_void,
+        ) };
+        match context.error {
+            Some(error) => Err(error),
+            None => io::result_from((), return_code),
+        }
+    }
+
+    /// Check that every block in use by the filesystem lies within the storage and can be
+    /// read in full.
+    ///
+    /// Storages that verify data on reads, like `ChecksummedStorage`, thereby check all
+    /// data in use. Inconsistencies littlefs finds while traversing are returned as errors,
+    /// usually `Corruption`, while bad blocks are counted in the report.
+    pub fn check_integrity(&self) -> Result<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+        let total_blocks = self.total_blocks();
+        self.traverse(|block| {
+            let block = block as usize;
+            report.blocks_visited += 1;
+            let healthy = if block >= total_blocks {
+                report.out_of_range += 1;
+                false
+            } else {
+                // the cache size divides the block size
+                let readable = (0..Storage::BLOCK_SIZE).step_by(buf.len()).all(|off| {
+                    self.storage.read(block * Storage::BLOCK_SIZE + off, &mut buf).is_ok()
+                });
+                if !readable {
+                    report.unreadable += 1;
+                }
+                readable
+            };
+            if !healthy && report.first_bad_block.is_none() {
+                report.first_bad_block = Some(block);
+            }
+            Ok(())
+        })?;
+        Ok(report)
+    }
+
+    /// Mark the blocks in use by the filesystem in `bitmap`, returning their number.
+    ///
+    /// Block `n` is bit `n % 8` of `bitmap[n / 8]`, so `bitmap` needs at least
+    /// `(total_blocks() + 7) / 8` bytes, otherwise this fails with `Invalid`. Blocks
+    /// visited more than once by the traversal, e.g. shared metadata, are counted once,
+    /// so this is at most the number of blocks `available_blocks` considers used.
+    /// Unmarked blocks hold no data, an image can be copied without them.
+    pub fn used_block_bitmap(&self, bitmap: &mut [u8]) -> Result<usize> {
+        let total_blocks = self.total_blocks();
+        if bitmap.len() * 8 < total_blocks {
+            return Err(io::Error::Invalid);
+        }
+        for byte in bitmap.iter_mut() {
+            *byte = 0;
+        }
+        let mut used = 0;
+        self.traverse(|block| {
+            if block as usize >= total_blocks {
+                return Err(io::Error::Corruption);
+            }
+            let (byte, bit) = (block as usize / 8, 1 << (block % 8));
+            if bitmap[byte] & bit == 0 {
+                bitmap[byte] |= bit;
+                used += 1;
+            }
+            Ok(())
+        })?;
+        Ok(used)
+    }
+
     /// Remove a file or directory.
     pub fn remove(&self, path: &Path) -> Result<()> {
         let return_code = unsafe { ll::lfs_remove(
@@ -303,6 +851,24 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         io::result_from((), return_code)
     }
 
+    /// Check whether the directory at `path` has no entries besides "." and "..".
+    ///
+    /// Returns `PathNotDir` if `path` is a file.
+    pub fn dir_is_empty(&self, path: &Path) -> Result<bool> {
+        self.read_dir_and_then(path, |read_dir| {
+            Ok(read_dir.nth(2).transpose()?.is_none())
+        })
+    }
+
+    /// Number of entries in the directory at `path`, not counting "." and "..".
+    ///
+    /// Returns `PathNotDir` if `path` is a file.
+    pub fn dir_entry_count(&self, path: &Path) -> Result<usize> {
+        self.read_dir_and_then(path, |read_dir| {
+            read_dir.skip(2).try_fold(0, |count, entry| entry.map(|_| count + 1))
+        })
+    }
+
     /// Remove a file or directory.
     pub fn remove_dir(&self, path: &Path) -> Result<()> {
         self.remove(path)
@@ -315,11 +881,26 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         self.remove_dir_all_where(path, &|_| true).map(|_| ())
     }
 
+    /// Recursively remove the files in `path` matching `predicate`, and the directories left empty.
+    ///
+    /// Fails with `DirectoryTooDeep` on reaching directories nested deeper than
+    /// `consts::MAX_RECURSION_DEPTH`, after removing what was visited before.
     #[cfg(feature = "dir-entry-path")]
     pub fn remove_dir_all_where<P>(&self, path: &Path, predicate: &P) -> Result<usize>
     where
         P: Fn(&DirEntry) -> bool,
     {
+        self.remove_dir_all_where_at(path, predicate, 0)
+    }
+
+    #[cfg(feature = "dir-entry-path")]
+    fn remove_dir_all_where_at<P>(&self, path: &Path, predicate: &P, depth: usize) -> Result<usize>
+    where
+        P: Fn(&DirEntry) -> bool,
+    {
+        if depth > crate::consts::MAX_RECURSION_DEPTH {
+            return Err(io::Error::DirectoryTooDeep);
+        }
         if !path.exists(self) {
             debug_now!("no such directory {}, early return", path);
             return Ok(0);
@@ -345,7 +926,7 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
                 }
                 if entry.file_type().is_dir() {
                     debug_now!("recursing into directory {}", &entry.path());
-                    files_removed += self.remove_dir_all_where(entry.path(), predicate)?;
+                    files_removed += self.remove_dir_all_where_at(entry.path(), predicate, depth + 1)?;
                     debug_now!("...back");
                 }
             }
@@ -356,7 +937,314 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
             self.remove_dir(path)?;
             debug_now!("..worked");
         }
-        Ok(files_removed)
+        Ok(files_removed)
+    }
+
+    /// Walk the tree below `root` and return the path of the first file or directory
+    /// matching `predicate`.
+    ///
+    /// Entries are visited depth-first, each directory before its contents.
+    /// The traversal stops as soon as a match is found. Fails with `DirectoryTooDeep`
+    /// on reaching directories nested deeper than `consts::MAX_RECURSION_DEPTH`.
+    pub fn find<P>(&self, root: &Path, mut predicate: P) -> Result<Option<PathBuf>>
+    where
+        P: FnMut(&Path, &Metadata) -> bool,
+    {
+        self.find_where(root, &mut predicate, 0)
+    }
+
+    fn find_where<P>(&self, dir: &Path, predicate: &mut P, depth: usize) -> Result<Option<PathBuf>>
+    where
+        P: FnMut(&Path, &Metadata) -> bool,
+    {
+        if depth > crate::consts::MAX_RECURSION_DEPTH {
+            return Err(io::Error::DirectoryTooDeep);
+        }
+        self.read_dir_and_then(dir, |read_dir| {
+            // skip "." and ".."
+            for entry in read_dir.skip(2) {
+                let entry = entry?;
+                let path = dir.join(entry.file_name());
+                if predicate(&path, &entry.metadata) {
+                    return Ok(Some(path));
+                }
+                if entry.file_type().is_dir() {
+                    if let Some(found) = self.find_where(&path, predicate, depth + 1)? {
+                        return Ok(Some(found));
+                    }
+                }
+            }
+            Ok(None)
+        })
+    }
+
+    /// Call `f` with the path and metadata of every file and directory below `root`.
+    ///
+    /// This is the callback version of `entries`, see there.
+    pub fn walk_dir<F>(&self, root: &Path, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Path, &Metadata) -> Result<()>,
+    {
+        for entry in self.entries(root) {
+            let (path, metadata) = entry?;
+            f(&path, &metadata)?;
+        }
+        Ok(())
+    }
+
+    /// Iterate over the paths and metadata of all files and directories below `root`.
+    ///
+    /// Directories are reported before their contents, and the entries of each directory
+    /// in littlefs' order, which sorts them by name, so the order only depends on the
+    /// contents. To keep the stack usage bounded, the walk keeps its own stack of
+    /// directories, and fails with `DirectoryTooDeep` when directories are nested deeper
+    /// than `consts::MAX_RECURSION_DEPTH`. The iterator ends after the first error.
+    pub fn entries<'s>(&'s self, root: &Path) -> Entries<'s, 'a, Storage> {
+        Entries {
+            fs: self,
+            stack: heapless::Vec::new(),
+            pending: Some(PathBuf::from(root)),
+        }
+    }
+
+    /// The total size of all files below `path`.
+    ///
+    /// This is the logical size, the sum of the files' lengths. It does not account for
+    /// metadata or partially used blocks, use `available_blocks` for on-disk usage.
+    pub fn dir_size(&self, path: &Path) -> Result<u64> {
+        let mut size = 0;
+        self.walk_dir(path, |_, metadata| {
+            if metadata.is_file() {
+                size += metadata.len() as u64;
+            }
+            Ok(())
+        })?;
+        Ok(size)
+    }
+
+    /// Write the files, directories and custom attributes below `root` to `archive`.
+    ///
+    /// The archive starts with the magic `LFA1`, followed by one record per entry in the
+    /// order of `entries`, and ends with a single `e` byte. Paths are relative to `root`
+    /// and prefixed by their length in a byte, integers are little-endian:
+    ///
+    /// - `d`, path: a directory
+    /// - `f`, path, length (`u32`), contents: a file
+    /// - `a`, id (`u8`), length (`u16`), data: a custom attribute of the preceding entry
+    ///
+    /// Attributes are found by probing all ids, as for `attributes`.
+    pub fn export_tar(&self, root: &Path, archive: &mut impl Extend<u8>) -> Result<()> {
+        archive.extend(ARCHIVE_MAGIC.iter().copied());
+        for entry in self.entries(root) {
+            let (path, metadata) = entry?;
+            // no panic by construction, entries are below `root`
+            let name = path.strip_prefix(root).unwrap().as_ref().as_bytes();
+            let tag = if metadata.is_dir() { ARCHIVE_DIR } else { ARCHIVE_FILE };
+            // names are shorter than `consts::PATH_MAX`
+            archive.extend([tag, name.len() as u8].iter().copied());
+            archive.extend(name.iter().copied());
+
+            if metadata.is_file() {
+                archive.extend((metadata.len() as u32).to_le_bytes().iter().copied());
+                self.open_file_and_then(&path, |file| {
+                    file.for_each_chunk(Storage::CACHE_SIZE::USIZE, |chunk| {
+                        archive.extend(chunk.iter().copied());
+                        Ok(())
+                    })
+                })?;
+            }
+
+            for attribute in self.attributes(&path)? {
+                let attribute = attribute?;
+                let data = attribute.data();
+                archive.extend([ARCHIVE_ATTRIBUTE, attribute.id()].iter().copied());
+                // attributes are at most `consts::ATTRBYTES_MAX` bytes
+                archive.extend((data.len() as u16).to_le_bytes().iter().copied());
+                archive.extend(data.iter().copied());
+            }
+        }
+        archive.extend(core::iter::once(ARCHIVE_END));
+        Ok(())
+    }
+
+    /// Recreate the entries of an archive written by `export_tar` below `root`.
+    ///
+    /// `root` must exist. Missing directories below it are created, existing files are
+    /// replaced, and attributes are set. Malformed archives, including ones ending early, fail with `Corruption`,
+    /// names too long to fit below `root` with `FilenameTooLong`. On any error, the
+    /// records before the failing one stay imported completely. A file the failing record
+    /// created is removed, while a file that existed before keeps its previous contents if
+    /// writing it fails, and is left with the partial contents if the archive ends early.
+    pub fn import_tar(&self, root: &Path, archive: &mut impl Iterator<Item = u8>) -> Result<()> {
+        let mut next = || archive.next().ok_or(io::Error::Corruption);
+        for &byte in ARCHIVE_MAGIC.iter() {
+            if next()? != byte {
+                return Err(io::Error::Corruption);
+            }
+        }
+
+        // entry the following attributes belong to
+        let mut last: Option<PathBuf> = None;
+        loop {
+            match next()? {
+                ARCHIVE_DIR => {
+                    let path = Self::archive_path(root, &mut next)?;
+                    self.create_dirs_below(root, &path, true)?;
+                    last = Some(path);
+                }
+                ARCHIVE_FILE => {
+                    let path = Self::archive_path(root, &mut next)?;
+                    self.create_dirs_below(root, &path, false)?;
+                    let mut len = [0u8; 4];
+                    for byte in len.iter_mut() {
+                        *byte = next()?;
+                    }
+                    let mut left = u32::from_le_bytes(len) as usize;
+
+                    let existed = path.exists(self);
+                    let mut created = false;
+                    let result = File::create_and_then(self, &path, |file| {
+                        created = !existed;
+                        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+                        while left > 0 {
+                            let chunk = cmp::min(left, buf.len());
+                            for byte in buf[..chunk].iter_mut() {
+                                *byte = next()?;
+                            }
+                            io::Write::write_all(file, &buf[..chunk])?;
+                            left -= chunk;
+                        }
+                        Ok(())
+                    });
+                    if result.is_err() && created {
+                        self.remove(&path).ok();
+                    }
+                    result?;
+                    last = Some(path);
+                }
+                ARCHIVE_ATTRIBUTE => {
+                    let path = last.as_ref().ok_or(io::Error::Corruption)?;
+                    let mut attribute = Attribute::new(next()?);
+                    let len = u16::from_le_bytes([next()?, next()?]) as usize;
+                    if len > crate::consts::ATTRBYTES_MAX as usize {
+                        return Err(io::Error::Corruption);
+                    }
+                    let mut data = [0u8; crate::consts::ATTRBYTES_MAX as usize];
+                    for byte in data[..len].iter_mut() {
+                        *byte = next()?;
+                    }
+                    attribute.set_data(&data[..len]);
+                    self.set_attribute(path, &attribute)?;
+                }
+                ARCHIVE_END => return Ok(()),
+                _ => return Err(io::Error::Corruption),
+            }
+        }
+    }
+
+    /// Read a path from an archive, see `export_tar`, and place it below `root`.
+    fn archive_path(root: &Path, next: &mut impl FnMut() -> Result<u8>) -> Result<PathBuf> {
+        let len = next()? as usize;
+        let mut name = [0u8; u8::MAX as usize];
+        for byte in name[..len].iter_mut() {
+            *byte = next()?;
+        }
+        // no empty or absolute names, and no `..` leading out of `root`
+        let name = PathBuf::try_from_bytes(&name[..len]).map_err(|_| io::Error::Corruption)?;
+        if name.as_ref().is_empty() || name.is_absolute() || name.normalize().is_err() {
+            return Err(io::Error::Corruption);
+        }
+
+        let root = root.as_ref().as_bytes();
+        let mut path: heapless::Vec<u8, { 2 * crate::consts::PATH_MAX + 1 }> = heapless::Vec::new();
+        // no panic by construction
+        path.extend_from_slice(root).unwrap();
+        if !root.is_empty() && !root.ends_with(b"/") {
+            path.push(b'/').unwrap();
+        }
+        path.extend_from_slice(name.as_ref().as_bytes()).unwrap();
+        Ok(PathBuf::try_from_bytes(&path)?)
+    }
+
+    /// Create the directories on the way from `root`, which must exist, to `path`,
+    /// including `path` itself if `inclusive`.
+    fn create_dirs_below(&self, root: &Path, path: &Path, inclusive: bool) -> Result<()> {
+        let bytes = path.as_ref().as_bytes();
+        let below = bytes.iter().enumerate().skip(root.as_ref().len() + 1)
+            .filter(|(_, &byte)| byte == b'/')
+            .map(|(i, _)| i);
+        let end = if inclusive { Some(bytes.len()) } else { None };
+        for i in below.chain(end) {
+            match self.create_dir(&PathBuf::from(&bytes[..i])) {
+                Ok(()) | Err(io::Error::EntryAlreadyExisted) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the oldest files in `dir` until their total size is at most `max_bytes`.
+    ///
+    /// The age of a file is given by its sequence number, a little-endian integer of up to
+    /// eight bytes stored in attribute `consts::SEQUENCE_ATTRIBUTE_ID`; files without one
+    /// count as oldest. Subdirectories are left alone. Returns the number of bytes freed.
+    pub fn enforce_budget(&self, dir: &Path, max_bytes: usize) -> Result<usize> {
+        let mut total = 0;
+        self.read_dir_and_then(dir, |read_dir| {
+            // skip "." and ".."
+            for entry in read_dir.skip(2) {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    total += entry.metadata().len();
+                }
+            }
+            Ok(())
+        })?;
+
+        let mut freed = 0;
+        while total > max_bytes {
+            let mut oldest: Option<(u64, PathBuf, usize)> = None;
+            self.read_dir_and_then(dir, |read_dir| {
+                // skip "." and ".."
+                for entry in read_dir.skip(2) {
+                    let entry = entry?;
+                    if !entry.file_type().is_file() {
+                        continue;
+                    }
+                    let path = dir.join(entry.file_name());
+                    let sequence = self.sequence_number(&path)?;
+                    if !matches!(&oldest, Some((oldest, _, _)) if *oldest <= sequence) {
+                        oldest = Some((sequence, path, entry.metadata().len()));
+                    }
+                }
+                Ok(())
+            })?;
+
+            match oldest {
+                Some((_, path, len)) => {
+                    self.remove(&path)?;
+                    total -= len;
+                    freed += len;
+                }
+                None => break,
+            }
+        }
+        Ok(freed)
+    }
+
+    fn sequence_number(&self, path: &Path) -> Result<u64> {
+        let mut bytes = [0u8; 8];
+        if let Some(attribute) = self.attribute(path, crate::consts::SEQUENCE_ATTRIBUTE_ID)? {
+            let data = attribute.data();
+            let len = cmp::min(data.len(), bytes.len());
+            bytes[..len].copy_from_slice(&data[..len]);
+        }
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Number of blocks holding the contents of file `path`, see `File::block_count`.
+    pub fn file_block_count(&self, path: &Path) -> Result<usize> {
+        self.open_file_and_then(path, |file| file.block_count())
     }
 
     /// Rename or move a file or directory.
@@ -369,6 +1257,191 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         io::result_from((),return_code)
     }
 
+    /// Copy the contents of file `from` to `to`, creating or truncating it.
+    ///
+    /// Returns the number of bytes copied. If copying fails midway, the partial
+    /// destination is removed if this call created it. A file that was there before
+    /// keeps its previous contents if writing it fails, as littlefs does not commit a file
+    /// after a failed write, but is committed with the partial contents if reading `from`
+    /// fails. Fails with `Invalid` if `from` and `to` are the same.
+    pub fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        if from == to {
+            return Err(io::Error::Invalid);
+        }
+        let existed = to.exists(self);
+        let mut created = false;
+        let result = self.open_file_and_then(from, |source| {
+            self.create_file_and_then(to, |destination| {
+                use io::Write;
+                created = !existed;
+                let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+                let mut copied = 0;
+                loop {
+                    let read = source.read(&mut buf)?;
+                    if read == 0 {
+                        return Ok(copied);
+                    }
+                    destination.write_all(&buf[..read])?;
+                    copied += read as u64;
+                }
+            })
+        });
+        if result.is_err() && created {
+            self.remove(to).ok();
+        }
+        result
+    }
+
+    /// Recursively copy the directory `from` and its contents to `to`.
+    ///
+    /// Missing parents of `to` are created, and `to` may already be a directory, in which
+    /// case files of the same name are overwritten. Custom attributes are not copied.
+    /// Fails with `DirectoryTooDeep` on reaching directories nested deeper than
+    /// `consts::MAX_RECURSION_DEPTH`, after copying what was visited before.
+    pub fn copy_dir_all(&self, from: &Path, to: &Path) -> Result<()> {
+        if !self.is_dir(from) {
+            return Err(io::Error::PathNotDir);
+        }
+        self.create_dir_all(to)?;
+        self.copy_dir_all_at(from, to, 0)
+    }
+
+    fn copy_dir_all_at(&self, from: &Path, to: &Path, depth: usize) -> Result<()> {
+        if depth > crate::consts::MAX_RECURSION_DEPTH {
+            return Err(io::Error::DirectoryTooDeep);
+        }
+        match self.create_dir(to) {
+            Err(io::Error::EntryAlreadyExisted) if self.is_dir(to) => {}
+            result => result?,
+        }
+        self.read_dir_and_then(from, |read_dir| {
+            // skip "." and ".."
+            for entry in read_dir.skip(2) {
+                let entry = entry?;
+                let source = from.join(entry.file_name());
+                let destination = to.join(entry.file_name());
+                if entry.file_type().is_dir() {
+                    self.copy_dir_all_at(&source, &destination, depth + 1)?;
+                } else {
+                    self.copy(&source, &destination)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Exchange the files `a` and `b`, which must be in the same directory.
+    ///
+    /// littlefs has no atomic exchange, so this takes three renames through `a.tmp~`, see
+    /// `recover_atomic`. Power loss in between leaves the temporary file behind, with either
+    /// `a` or `b` missing; `recover_atomic` then completes or rolls back the swap, so each
+    /// name ends up with either its old or its new contents. Call it on boot, a second swap
+    /// in the same directory before then may replace the leftover temporary file.
+    ///
+    /// Fails with `Invalid` if `a` and `b` are the same, or either of them is not a file.
+    pub fn rename_swap(&self, a: &Path, b: &Path) -> Result<()> {
+        let a_bytes = a.as_ref().as_bytes();
+        let b_bytes = b.as_ref().as_bytes();
+        let b_name_start = b_bytes.iter().rposition(|&byte| byte == b'/').map_or(0, |i| i + 1);
+        if a == b || a.parent() != b.parent() || b_name_start == b_bytes.len() {
+            return Err(io::Error::Invalid);
+        }
+
+        let suffix = crate::consts::ATOMIC_TEMP_SUFFIX.as_bytes();
+        let mut temp = [0u8; crate::consts::PATH_MAX];
+        if a_bytes.len() + suffix.len() > temp.len() {
+            return Err(io::Error::FilenameTooLong);
+        }
+        temp[..a_bytes.len()].copy_from_slice(a_bytes);
+        temp[a_bytes.len()..][..suffix.len()].copy_from_slice(suffix);
+        let temp = PathBuf::from(&temp[..a_bytes.len() + suffix.len()]);
+
+        if !self.metadata(a)?.is_file() || !self.metadata(b)?.is_file() {
+            return Err(io::Error::Invalid);
+        }
+        let mut partner = Attribute::new(crate::consts::SWAP_PARTNER_ATTRIBUTE_ID);
+        self.set_attribute(a, partner.set_data(&b_bytes[b_name_start..]))?;
+        self.rename(a, &temp)?;
+        self.rename(b, a)?;
+        self.rename(&temp, b)?;
+        // only the temporary file's attribute is ever looked at
+        self.remove_attribute(b, crate::consts::SWAP_PARTNER_ATTRIBUTE_ID)
+    }
+
+    /// Complete or roll back the swaps in `dir` that were interrupted, e.g. by power loss.
+    ///
+    /// Swapping `a` and `b` first stores the name of `b` in attribute
+    /// `consts::SWAP_PARTNER_ATTRIBUTE_ID` of `a`, then renames `a` to `a.tmp~` (see
+    /// `consts::ATOMIC_TEMP_SUFFIX`), `b` to `a`, and finally `a.tmp~` to `b`. A leftover
+    /// temporary file is moved back to `a` if that is missing, or on to `b` if that is missing.
+    /// Temporary files without the attribute were not left by a swap, and are not touched,
+    /// neither are those whose attribute is not a valid file name.
+    ///
+    /// Call this on boot, before using the files in `dir`.
+    /// Returns the number of swaps dealt with. If both `a` and `b` exist for some temporary
+    /// file, it is left in place for the caller to resolve, and this fails with
+    /// `EntryAlreadyExisted` after dealing with the other swaps.
+    pub fn recover_atomic(&self, dir: &Path) -> Result<usize> {
+        let suffix_len = crate::consts::ATOMIC_TEMP_SUFFIX.len();
+        let mut recovered = 0;
+        let mut conflicts = 0;
+        while let Some((temp, partner)) = self.next_atomic_temp(dir, conflicts)? {
+            let temp_bytes = temp.as_bytes();
+            let target = PathBuf::from(&temp_bytes[..temp_bytes.len() - suffix_len]);
+            let partner = dir.join(&partner);
+            let destination = if !target.exists(self) {
+                // first rename happened: roll back
+                target
+            } else if !partner.exists(self) {
+                // second rename happened: complete
+                partner
+            } else {
+                conflicts += 1;
+                continue;
+            };
+            self.rename(&temp, &destination)?;
+            self.remove_attribute(&destination, crate::consts::SWAP_PARTNER_ATTRIBUTE_ID)?;
+            recovered += 1;
+        }
+        if conflicts > 0 {
+            return Err(io::Error::EntryAlreadyExisted);
+        }
+        Ok(recovered)
+    }
+
+    /// The temporary file left by a swap in `dir` after the first `skip` ones, with its partner.
+    fn next_atomic_temp(&self, dir: &Path, mut skip: usize) -> Result<Option<(PathBuf, PathBuf)>> {
+        self.read_dir_and_then(dir, |read_dir| {
+            // skip "." and ".."
+            for entry in read_dir.skip(2) {
+                let entry = entry?;
+                let name = entry.file_name().as_bytes();
+                if !entry.file_type().is_file()
+                    || !name.ends_with(crate::consts::ATOMIC_TEMP_SUFFIX.as_bytes())
+                {
+                    continue;
+                }
+                let temp = dir.join(entry.file_name());
+                let partner = match self.attribute(&temp, crate::consts::SWAP_PARTNER_ATTRIBUTE_ID)? {
+                    Some(partner) => partner,
+                    None => continue,
+                };
+                // the attribute comes from storage, it may not be a file name
+                let partner = match PathBuf::try_from_bytes(partner.data()) {
+                    Ok(partner) if !partner.as_bytes().is_empty() && !partner.as_bytes().contains(&b'/') => {
+                        partner
+                    }
+                    _ => continue,
+                };
+                if skip == 0 {
+                    return Ok(Some((temp, partner)));
+                }
+                skip -= 1;
+            }
+            Ok(None)
+        })
+    }
+
     /// Given a path, query the filesystem to get information about a file or directory.
     ///
     /// To read user attributes, use
@@ -393,6 +1466,64 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         io::result_from((), return_code).map(|_| info.into())
     }
 
+    /// Safety-hatch to call littlefs functions this wrapper does not expose.
+    ///
+    /// `f` gets the mounted filesystem state and its configuration, and returns a littlefs
+    /// error code, zero on success.
+    ///
+    /// # Safety
+    ///
+    /// The pointers are only valid during the call, and `f` must leave the filesystem
+    /// consistent with this wrapper: no files or directories may be left open, and the
+    /// filesystem must stay mounted with the same configuration.
+    pub unsafe fn with_raw(
+        &self,
+        f: impl FnOnce(*mut ll::lfs_t, *mut ll::lfs_config) -> cty::c_int,
+    ) -> Result<()> {
+        let mut alloc = self.alloc.borrow_mut();
+        let alloc = &mut **alloc;
+        let return_code = f(&mut alloc.state, &mut alloc.config);
+        io::result_from((), return_code)
+    }
+
+    /// Whether a file or directory exists at `path`.
+    ///
+    /// Any error, not just `NoSuchEntry`, counts as absence. Use `try_exists` to tell
+    /// I/O errors or corruption apart from a missing entry.
+    pub fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    /// Whether a file or directory exists at `path`, returning errors other than `NoSuchEntry`.
+    pub fn try_exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path) {
+            Ok(_) => Ok(true),
+            Err(io::Error::NoSuchEntry) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Whether `path` is a file; any error counts as `false`, like for `exists`.
+    pub fn is_file(&self, path: &Path) -> bool {
+        matches!(self.metadata(path), Ok(metadata) if metadata.is_file())
+    }
+
+    /// Whether `path` is a directory; any error counts as `false`, like for `exists`.
+    pub fn is_dir(&self, path: &Path) -> bool {
+        matches!(self.metadata(path), Ok(metadata) if metadata.is_dir())
+    }
+
+    /// Create an empty file at `path` if there is none, leaving an existing file untouched.
+    ///
+    /// Like for lock files, only the existence of the file matters; unlike the Unix tool,
+    /// this does not update the time stored by `set_modified`.
+    pub fn touch(&self, path: &Path) -> Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open_and_then(self, path, |_| Ok(()))
+    }
+
     pub fn create_file_and_then<R>(
         &self,
         path: &Path,
@@ -403,6 +1534,10 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         File::create_and_then(self, path, f)
     }
 
+    /// Open the file at `path` read-only and pass it to `f`.
+    ///
+    /// A file may be opened several times, e.g. by nesting calls, each handle keeping
+    /// its own position. Only one handle at a time may write, see `OpenOptions::open`.
     pub fn open_file_and_then<R>(
         &self,
         path: &Path,
@@ -461,6 +1596,23 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         unreachable!();
     }
 
+    /// Iterate over the custom attributes of `path`.
+    ///
+    /// littlefs cannot enumerate attributes, so this probes all 256 ids in turn, at the
+    /// cost of one metadata lookup each. Fails early if `path` does not exist.
+    pub fn attributes<'s>(&'s self, path: &'s Path) -> Result<Attributes<'s, 'a, Storage>> {
+        self.metadata(path)?;
+        Ok(Attributes { fs: self, path, ids: 0..=u8::MAX })
+    }
+
+    /// Remove all custom attributes of `path`, probing every id as `attributes` does.
+    pub fn clear_attributes(&self, path: &Path) -> Result<()> {
+        for attribute in self.attributes(path)? {
+            self.remove_attribute(path, attribute?.id())?;
+        }
+        Ok(())
+    }
+
     /// Remove attribute.
     pub fn remove_attribute(
         &self,
@@ -494,9 +1646,48 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         io::result_from((), return_code)
     }
 
+    /// Record the modification time of `path`, in seconds since the Unix epoch.
+    ///
+    /// littlefs keeps no timestamps itself, this stores the time as little-endian `u64`
+    /// in attribute `consts::MODIFIED_ATTRIBUTE_ID`.
+    pub fn set_modified(&self, path: &Path, unix_secs: u64) -> Result<()> {
+        let mut attribute = Attribute::new(crate::consts::MODIFIED_ATTRIBUTE_ID);
+        attribute.set_data(&unix_secs.to_le_bytes());
+        self.set_attribute(path, &attribute)
+    }
+
+    /// The modification time of `path` recorded by `set_modified`, if any.
+    pub fn modified(&self, path: &Path) -> Result<Option<u64>> {
+        let attribute = match self.attribute(path, crate::consts::MODIFIED_ATTRIBUTE_ID)? {
+            Some(attribute) => attribute,
+            None => return Ok(None),
+        };
+        let mut bytes = [0u8; 8];
+        if attribute.data().len() != bytes.len() {
+            return Err(io::Error::Corruption);
+        }
+        bytes.copy_from_slice(attribute.data());
+        Ok(Some(u64::from_le_bytes(bytes)))
+    }
+
+    /// Translate the outcome of a storage access of `len` bytes for littlefs.
+    ///
+    /// Short accesses are reported as `Io` errors, instead of letting littlefs carry on
+    /// with data that was never read or written.
+    fn storage_return_code(operation: &str, result: Result<usize>, len: usize) -> cty::c_int {
+        let error = match result {
+            Ok(n) if n == len => return 0,
+            Ok(_) => io::Error::Io,
+            Err(error) => error,
+        };
+        warn!("storage {} of {} bytes failed: {:?}", operation, len, error);
+        error.code()
+    }
 
     /// C callback interface used by LittleFS to read data with the lower level system below the
     /// filesystem.
+    ///
+    /// Accesses not aligned to `Storage::READ_SIZE` fail with `Invalid`.
     extern "C" fn lfs_config_read(
         c: *const ll::lfs_config,
         block: ll::lfs_block_t,
@@ -504,20 +1695,25 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         buffer: *mut cty::c_void,
         size: ll::lfs_size_t,
     ) -> cty::c_int {
-        // println!("in lfs_config_read for {} bytes", size);
-        let storage = unsafe { &*((*c).context as *const Storage) };
+        trace!("read block {} off {} size {}", block, off, size);
         debug_assert!(!c.is_null());
+        let storage = unsafe { &*((*c).context as *const Storage) };
         let block_size = unsafe { c.read().block_size };
         let off = (block * block_size + off) as usize;
-        let buf: &mut [u8] = unsafe { slice::from_raw_parts_mut(buffer as *mut u8, size as usize) };
+        let size = size as usize;
+        if off % Storage::READ_SIZE != 0 || size % Storage::READ_SIZE != 0 {
+            warn!("misaligned read at {} of {} bytes", off, size);
+            return io::Error::Invalid.code();
+        }
+        let buf: &mut [u8] = unsafe { slice::from_raw_parts_mut(buffer as *mut u8, size) };
 
-        // TODO
-        storage.read(off, buf).unwrap();
-        0
+        Self::storage_return_code("read", storage.read(off, buf), size)
     }
 
     /// C callback interface used by LittleFS to program data with the lower level system below the
     /// filesystem.
+    ///
+    /// Accesses not aligned to `Storage::WRITE_SIZE` fail with `Invalid`.
     extern "C" fn lfs_config_prog(
         c: *const ll::lfs_config,
         block: ll::lfs_block_t,
@@ -525,17 +1721,19 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         buffer: *const cty::c_void,
         size: ll::lfs_size_t,
     ) -> cty::c_int {
-        // println!("in lfs_config_prog");
-        let storage = unsafe { &mut *((*c).context as *mut Storage) };
+        trace!("prog block {} off {} size {}", block, off, size);
         debug_assert!(!c.is_null());
-        // let block_size = unsafe { c.read().block_size };
-        let block_size = Storage::BLOCK_SIZE as u32;
+        let storage = unsafe { &mut *((*c).context as *mut Storage) };
+        let block_size = unsafe { c.read().block_size };
         let off = (block * block_size + off) as usize;
-        let buf: &[u8] = unsafe { slice::from_raw_parts(buffer as *const u8, size as usize) };
+        let size = size as usize;
+        if off % Storage::WRITE_SIZE != 0 || size % Storage::WRITE_SIZE != 0 {
+            warn!("misaligned prog at {} of {} bytes", off, size);
+            return io::Error::Invalid.code();
+        }
+        let buf: &[u8] = unsafe { slice::from_raw_parts(buffer as *const u8, size) };
 
-        // TODO
-        storage.write(off, buf).unwrap();
-        0
+        Self::storage_return_code("prog", storage.write(off, buf), size)
     }
 
     /// C callback interface used by LittleFS to erase data with the lower level system below the
@@ -544,25 +1742,80 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         c: *const ll::lfs_config,
         block: ll::lfs_block_t,
     ) -> cty::c_int {
-        // println!("in lfs_config_erase");
+        trace!("erase block {}", block);
+        debug_assert!(!c.is_null());
         let storage = unsafe { &mut *((*c).context as *mut Storage) };
-        let off = block as usize * Storage::BLOCK_SIZE as usize;
+        let block_size = unsafe { c.read().block_size } as usize;
+        let off = block as usize * block_size;
 
-        // TODO
-        storage.erase(off, Storage::BLOCK_SIZE as usize).unwrap();
-        0
+        Self::storage_return_code("erase", storage.erase(off, block_size), block_size)
     }
 
     /// C callback interface used by LittleFS to sync data with the lower level interface below the
     /// filesystem. Note that this function currently does nothing.
     extern "C" fn lfs_config_sync(_c: *const ll::lfs_config) -> i32 {
-        // println!("in lfs_config_sync");
+        trace!("sync");
         // Do nothing; we presume that data is synchronized.
         0
     }
 
 }
 
+/// Size limits of a filesystem, see `Filesystem::limits`.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Limits {
+    /// Longest file name, in bytes.
+    pub name_max: usize,
+    /// Largest file size, in bytes.
+    pub file_max: usize,
+    /// Largest custom attribute, in bytes.
+    pub attr_max: usize,
+}
+
+/// Outcome of `Filesystem::check_integrity`.
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IntegrityReport {
+    /// Blocks visited by the traversal, counting blocks visited more than once.
+    pub blocks_visited: usize,
+    /// Visited blocks beyond the end of the storage.
+    pub out_of_range: usize,
+    /// Visited blocks that failed to read, e.g. on a checksum mismatch.
+    pub unreadable: usize,
+    /// The first block that was out of range or unreadable.
+    pub first_bad_block: Option<usize>,
+}
+
+impl IntegrityReport {
+    /// Whether no anomalies were found.
+    pub fn is_clean(&self) -> bool {
+        self.out_of_range == 0 && self.unreadable == 0
+    }
+}
+
+struct TraverseContext<'f, F> {
+    f: &'f mut F,
+    error: Option<io::Error>,
+}
+
+/// C callback interface used by `lfs_fs_traverse`, forwarding to the closure
+/// passed to `Filesystem::traverse`.
+extern "C" fn lfs_traverse_callback<F>(data: *mut cty::c_void, block: ll::lfs_block_t) -> cty::c_int
+where
+    F: FnMut(ll::lfs_block_t) -> Result<()>,
+{
+    let context = unsafe { &mut *(data as *mut TraverseContext<'_, F>) };
+    match (context.f)(block) {
+        Ok(()) => 0,
+        Err(error) => {
+            context.error = Some(error);
+            // any error code aborts the traversal
+            ll::lfs_error_LFS_ERR_IO
+        }
+    }
+}
+
 #[derive(Clone,Debug,Eq,PartialEq)]
 /// Custom user attribute that can be set on files and directories.
 ///
@@ -578,6 +1831,89 @@ pub struct Attribute {
     size: usize,
 }
 
+/// Iterator over the custom attributes of a file or directory, see
+/// [`Filesystem::attributes`](struct.Filesystem.html#method.attributes).
+pub struct Attributes<'s, 'a, S: driver::Storage> {
+    fs: &'s Filesystem<'a, S>,
+    path: &'s Path,
+    ids: core::ops::RangeInclusive<u8>,
+}
+
+impl<S: driver::Storage> Iterator for Attributes<'_, '_, S> {
+    type Item = Result<Attribute>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for id in &mut self.ids {
+            if let Some(attribute) = self.fs.attribute(self.path, id).transpose() {
+                return Some(attribute);
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over the files and directories below a directory, see
+/// [`Filesystem::entries`](struct.Filesystem.html#method.entries).
+pub struct Entries<'s, 'a, S: driver::Storage> {
+    fs: &'s Filesystem<'a, S>,
+    /// Directories being walked, with the position of the entry visited last.
+    stack: heapless::Vec<(PathBuf, Option<usize>), { crate::consts::MAX_RECURSION_DEPTH + 1 }>,
+    /// Directory reported last, to descend into next.
+    pending: Option<PathBuf>,
+}
+
+impl<S: driver::Storage> Iterator for Entries<'_, '_, S> {
+    type Item = Result<(PathBuf, Metadata)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.advance().transpose();
+        if let Some(Err(_)) = next {
+            self.stack.clear();
+            self.pending = None;
+        }
+        next
+    }
+}
+
+impl<S: driver::Storage> Entries<'_, '_, S> {
+    fn advance(&mut self) -> Result<Option<(PathBuf, Metadata)>> {
+        if let Some(dir) = self.pending.take() {
+            self.stack.push((dir, None)).map_err(|_| io::Error::DirectoryTooDeep)?;
+        }
+        while let Some((dir, last)) = self.stack.last_mut() {
+            // only one directory is open at a time, seeking skips the entries already
+            // visited without reading them; littlefs refuses to seek past the last entry,
+            // so this returns to the entry visited last instead of the one after it
+            let entry = self.fs.read_dir_and_then(dir, |read_dir| {
+                match *last {
+                    // skip "." and ".."
+                    None => read_dir.nth(1).transpose()?,
+                    Some(position) => {
+                        read_dir.seek(position)?;
+                        read_dir.next().transpose()?
+                    }
+                };
+                *last = Some(read_dir.tell()?);
+                read_dir.next().transpose()
+            })?;
+            let entry = match entry {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+
+            let path = dir.join(entry.file_name());
+            if entry.file_type().is_dir() {
+                self.pending = Some(path.clone());
+            }
+            return Ok(Some((path, entry.metadata())));
+        }
+        Ok(None)
+    }
+}
+
 impl Attribute {
     pub fn new(id: u8) -> Self {
         Attribute {
@@ -637,6 +1973,7 @@ pub struct FileAllocation<S: driver::Storage>
     cache: Bytes<S::CACHE_SIZE>,
     state: ll::lfs_file_t,
     config: ll::lfs_file_config,
+    next_writer: Cell<*mut FileAllocation<S>>,
 }
 
 impl<S: driver::Storage> Default for FileAllocation<S> {
@@ -651,6 +1988,16 @@ impl<S: driver::Storage> FileAllocation<S> {
         debug_assert!(cache_size > 0);
         unsafe { mem::MaybeUninit::zeroed().assume_init() }
     }
+
+    /// Return to the state of a new allocation, dropping anything left by a previous file.
+    fn reset(&mut self) {
+        // NOTE(unsafe) the littlefs structs are plain data, valid when zeroed
+        unsafe {
+            self.state = mem::MaybeUninit::zeroed().assume_init();
+            self.config = mem::MaybeUninit::zeroed().assume_init();
+        }
+        self.next_writer = Cell::new(ptr::null_mut());
+    }
 }
 
 pub struct File<'a, 'b, S: driver::Storage>
@@ -715,6 +2062,21 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage>
             .open(fs, alloc, path)
     }
 
+    /// Open a file for reading in a reusable allocation, see
+    /// `OpenOptions::open_with_allocation_and_then`.
+    pub fn open_with_allocation_and_then<R>(
+        fs: &Filesystem<'a, Storage>,
+        alloc: &mut FileAllocation<Storage>,
+        path: &Path,
+        f: impl FnOnce(&File<'_, '_, Storage>) -> Result<R>,
+    ) ->
+        Result<R>
+    {
+        OpenOptions::new()
+            .read(true)
+            .open_with_allocation_and_then(fs, alloc, path, f)
+    }
+
     pub fn create_and_then<R>(
         fs: &Filesystem<'a, Storage>,
         path: &Path,
@@ -749,10 +2111,14 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage>
     ///   not LFS_F_OPENED...
     pub unsafe fn close(self) -> Result<()>
     {
+        let mut alloc = self.alloc.borrow_mut();
         let return_code = ll::lfs_file_close(
             &mut self.fs.alloc.borrow_mut().state,
-            &mut self.alloc.borrow_mut().state,
+            &mut alloc.state,
         );
+        // littlefs forgets the file even if the final sync fails
+        self.fs.open_files.set(self.fs.open_files.get() - 1);
+        self.fs.unlink_writer(&mut **alloc);
         io::result_from((), return_code)
     }
 
@@ -765,6 +2131,39 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage>
         io::result_from((), return_code)
     }
 
+    /// The current position in the file.
+    ///
+    /// In append mode, writes move it to the new end of the file.
+    pub fn position(&self) -> Result<usize> {
+        let return_code = unsafe { ll::lfs_file_tell(
+            &mut self.fs.alloc.borrow_mut().state,
+            &mut self.alloc.borrow_mut().state,
+        ) };
+        io::result_from(return_code as usize, return_code)
+    }
+
+    /// Move the position back to the start of the file, like seeking to offset zero.
+    ///
+    /// Pending writes are kept, and written out first.
+    pub fn rewind(&self) -> Result<()> {
+        let return_code = unsafe { ll::lfs_file_rewind(
+            &mut self.fs.alloc.borrow_mut().state,
+            &mut self.alloc.borrow_mut().state,
+        ) };
+        io::result_from((), return_code)
+    }
+
+    /// Size of the file in bytes, found by seeking to the end and back.
+    ///
+    /// Unlike `len`, this moves the in-flight position through littlefs; the position
+    /// is restored afterwards.
+    pub fn size_seek(&self) -> Result<usize> {
+        let position = self.seek(io::SeekFrom::Current(0))?;
+        let size = self.seek(io::SeekFrom::End(0))?;
+        self.seek(io::SeekFrom::Start(position as u32))?;
+        Ok(size)
+    }
+
     /// Size of the file in bytes.
     pub fn len(&self) -> Result<usize> {
         let return_code = unsafe { ll::lfs_file_size(
@@ -774,6 +2173,30 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage>
         io::result_from(return_code as usize, return_code)
     }
 
+    /// Number of blocks holding the file's contents.
+    ///
+    /// Small files are inlined in their directory's metadata pair and occupy no blocks
+    /// of their own. Larger files are stored as a CTZ skip-list, whose block count follows
+    /// from the file size; this mirrors `lfs_ctz_index`, as littlefs does not expose
+    /// per-file block iteration. The directory's metadata blocks are never counted.
+    pub fn block_count(&self) -> Result<usize> {
+        let inline = self.alloc.borrow().state.flags & ll::lfs_open_flags_LFS_F_INLINE != 0;
+        let size = self.len()?;
+        if inline || size == 0 {
+            return Ok(0);
+        }
+
+        // each block but the first starts with up to two skip-list pointers
+        let data_per_block = Storage::BLOCK_SIZE - 2 * 4;
+        let last = size - 1;
+        let index = last / data_per_block;
+        if index == 0 {
+            return Ok(1);
+        }
+        let pointers = 4 * ((index - 1).count_ones() as usize + 2);
+        Ok((last - pointers) / data_per_block + 1)
+    }
+
     /// Truncates or extends the underlying file, updating the size of this file to become size.
     ///
     /// If the size is less than the current file's size, then the file will be shrunk. If it is
@@ -788,6 +2211,29 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage>
         io::result_from((), return_code)
     }
 
+    /// Read the rest of the file in chunks of `chunk_size` bytes, passing each to `f`.
+    ///
+    /// Chunks are read into a stack buffer, so `chunk_size` is capped at `CACHE_SIZE`.
+    /// The final chunk may be shorter. Returns `Invalid` if `chunk_size` is zero.
+    pub fn for_each_chunk(
+        &self,
+        chunk_size: usize,
+        mut f: impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+        if chunk_size == 0 {
+            return Err(io::Error::Invalid);
+        }
+        let chunk_size = cmp::min(chunk_size, buf.len());
+        loop {
+            let read = self.read(&mut buf[..chunk_size])?;
+            if read == 0 {
+                return Ok(());
+            }
+            f(&buf[..read])?;
+        }
+    }
+
     // This belongs in `io::Read` but really don't want that to have a generic parameter
     pub fn read_to_end<const N: usize>(&self, buf: &mut heapless::Vec<u8, N>) -> Result<usize> {
         // My understanding of
@@ -798,20 +2244,67 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage>
         // no panic by construction
         buf.resize_default(buf.capacity()).unwrap();
         // use io::Read;
-        let read = self.read(&mut buf[had..])?;
+        let read = self.read(&mut buf[had..]);
         // no panic by construction
-        buf.resize_default(had + read).unwrap();
+        buf.resize_default(had + *read.as_ref().unwrap_or(&0)).unwrap();
+        read
+    }
+
+    /// Like `read_to_end`, for files holding UTF-8 text.
+    ///
+    /// Returns `Invalid` if the bytes read are not valid UTF-8, leaving `buf` as it was.
+    /// This includes a character cut off because `buf` is full.
+    pub fn read_to_string<const N: usize>(&self, buf: &mut heapless::String<N>) -> Result<usize> {
+        // NOTE(unsafe) the bytes are validated before returning
+        let bytes = unsafe { buf.as_mut_vec() };
+        let had = bytes.len();
+        let read = self.read_to_end(bytes)?;
+        if core::str::from_utf8(&bytes[had..]).is_err() {
+            bytes.truncate(had);
+            return Err(io::Error::Invalid);
+        }
         Ok(read)
     }
 
+    /// Read up to `buf.len()` bytes, returning how many were read.
+    ///
+    /// Sequential small reads need no extra read-ahead: on a miss, littlefs fills the
+    /// whole per-file cache (`Storage::CACHE_SIZE` bytes, up to the end of the block),
+    /// so the following reads are served from memory. To read further ahead, increase
+    /// `CACHE_SIZE`.
     pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
         <Self as io::Read>::read(self, buf)
     }
 
+    /// Read into a possibly uninitialized buffer, returning the part that was read.
+    ///
+    /// This saves zeroing large buffers beforehand; only the bytes littlefs wrote are
+    /// exposed.
+    pub fn read_uninit<'c>(&self, buf: &'c mut [mem::MaybeUninit<u8>]) -> Result<&'c mut [u8]> {
+        let return_code = unsafe { ll::lfs_file_read(
+            &mut self.fs.alloc.borrow_mut().state,
+            &mut self.alloc.borrow_mut().state,
+            buf.as_mut_ptr() as *mut cty::c_void,
+            buf.len() as u32,
+        ) };
+        let read = io::result_from(return_code as usize, return_code)?;
+        debug_assert!(read <= buf.len());
+        // NOTE(unsafe) littlefs initialized the first `read` bytes
+        Ok(unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, read) })
+    }
+
     pub fn seek(&self, pos: io::SeekFrom) -> Result<usize> {
         <Self as io::Seek>::seek(self, pos)
     }
 
+    /// Write `buf` at the current position, returning how many bytes were written.
+    ///
+    /// Fails with `BadFileDescriptor` if the file was not opened for writing.
+    ///
+    /// Small writes are gathered in the per-file cache and programmed once it is full or
+    /// the file is synced. littlefs sizes this cache, like all others, by `CACHE_SIZE`
+    /// and would not use a larger buffer, so to program in larger units, increase
+    /// `CACHE_SIZE`.
     pub fn write(&self, buf: &[u8]) -> Result<usize> {
         <Self as io::Write>::write(self, buf)
     }
@@ -828,12 +2321,41 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage>
 #[derive(Clone,Debug,Eq,PartialEq)]
 pub struct OpenOptions (FileOpenFlags);
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for OpenOptions {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "OpenOptions({=u32:#x})", self.0.bits())
+    }
+}
+
 impl Default for OpenOptions {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Closes the file when dropped, also when unwinding from a panic.
+///
+/// Otherwise littlefs would keep the file's allocation in its list of open files.
+struct CloseGuard<'a, 'b, S: driver::Storage>(Option<File<'a, 'b, S>>);
+
+impl<S: driver::Storage> CloseGuard<'_, '_, S> {
+    fn close(mut self) -> Result<()> {
+        match self.0.take() {
+            Some(file) => unsafe { file.close() },
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S: driver::Storage> Drop for CloseGuard<'_, '_, S> {
+    fn drop(&mut self) {
+        if let Some(file) = self.0.take() {
+            unsafe { file.close().ok() };
+        }
+    }
+}
+
 impl OpenOptions {
 
     /// Open the file with the options previously specified, keeping references.
@@ -847,6 +2369,12 @@ impl OpenOptions {
     ///   closing removes them from there
     /// - since littlefs is supposed to be *fail-safe*, we can't just close files in
     ///   Drop and panic if something went wrong.
+    ///
+    /// Only one handle may write to a file at a time, opening another one for writing
+    /// fails with `FileBusy`. Read handles opened alongside a writer are not protected:
+    /// once the writer commits, they may read stale data.
+    ///
+    /// Fails with `TooManyOpenFiles` if `S::MAX_OPEN_FILES` files are open already.
     pub unsafe fn open<'a, 'b, S: driver::Storage>(
         &self,
         fs: &'b Filesystem<'a, S>,
@@ -855,6 +2383,14 @@ impl OpenOptions {
     ) ->
         Result<File<'a, 'b, S>>
     {
+        if fs.open_files.get() >= S::MAX_OPEN_FILES {
+            return Err(io::Error::TooManyOpenFiles);
+        }
+        let writes = self.0.contains(FileOpenFlags::WRITE);
+        // checked before opening, as opening commits e.g. a truncation
+        if writes && fs.has_writer(alloc, path) {
+            return Err(io::Error::FileBusy);
+        }
         alloc.config.buffer = &mut alloc.cache as *mut _ as *mut cty::c_void;
 
         let return_code = ll::lfs_file_opencfg(
@@ -865,32 +2401,99 @@ impl OpenOptions {
                 &alloc.config,
         );
 
+        if return_code == 0 {
+            fs.open_files.set(fs.open_files.get() + 1);
+            if writes {
+                alloc.next_writer.set(fs.writers.get());
+                fs.writers.set(&mut *alloc);
+            }
+        }
+
         let file = File {
             alloc: RefCell::new(alloc),
             fs,
         };
 
-        io::result_from(file, return_code)
+        io::result_from(file, return_code)
+    }
+
+    /// (Hopefully) safe abstraction around `open`.
+    pub fn open_and_then<'a, R, S: driver::Storage>(
+        &self,
+        fs: &Filesystem<'a, S>,
+        path: &Path,
+        f: impl FnOnce(&File<'a, '_, S>) -> Result<R>,
+    )
+        -> Result<R>
+    {
+        let mut alloc = FileAllocation::new(); // lifetime 'c
+        self.open_with_allocation_and_then(fs, &mut alloc, path, f)
+    }
+
+    /// Like `open_and_then`, but with a caller-provided allocation.
+    ///
+    /// The allocation is reset before opening, so one allocation can be used for
+    /// any number of files in sequence, e.g. in a loop over a directory.
+    pub fn open_with_allocation_and_then<'a, R, S: driver::Storage>(
+        &self,
+        fs: &Filesystem<'a, S>,
+        alloc: &mut FileAllocation<S>,
+        path: &Path,
+        f: impl FnOnce(&File<'a, '_, S>) -> Result<R>,
+    )
+        -> Result<R>
+    {
+        alloc.reset();
+        self.open_allocated_and_then(fs, alloc, path, f)
+    }
+
+    /// Like `open_and_then`, with custom attributes that are committed atomically with
+    /// the file's contents.
+    ///
+    /// If the file is opened for reading, the attributes' data is replaced by the stored
+    /// values (up to the attributes' current sizes) on opening. If it is opened for
+    /// writing, the attributes' data is written whenever the file is synced or closed.
+    pub fn open_with_attributes_and_then<'a, R, S: driver::Storage, const N: usize>(
+        &self,
+        fs: &Filesystem<'a, S>,
+        path: &Path,
+        attributes: &mut [Attribute; N],
+        f: impl FnOnce(&File<'a, '_, S>) -> Result<R>,
+    )
+        -> Result<R>
+    {
+        let mut attrs: heapless::Vec<ll::lfs_attr, N> = heapless::Vec::new();
+        for attribute in attributes.iter_mut() {
+            let attr = ll::lfs_attr {
+                type_: attribute.id,
+                buffer: &mut attribute.data as *mut _ as *mut cty::c_void,
+                size: attribute.size as u32,
+            };
+            // no panic by construction
+            attrs.push(attr).unwrap();
+        }
+        let mut alloc = FileAllocation::new();
+        alloc.config.attrs = attrs.as_mut_ptr();
+        alloc.config.attr_count = N as u32;
+        self.open_allocated_and_then(fs, &mut alloc, path, f)
     }
 
-    /// (Hopefully) safe abstraction around `open`.
-    pub fn open_and_then<'a, R, S: driver::Storage>(
+    fn open_allocated_and_then<'a, R, S: driver::Storage>(
         &self,
         fs: &Filesystem<'a, S>,
+        alloc: &mut FileAllocation<S>,
         path: &Path,
         f: impl FnOnce(&File<'a, '_, S>) -> Result<R>,
     )
         -> Result<R>
     {
-        let mut alloc = FileAllocation::new(); // lifetime 'c
-        let mut file = unsafe { self.open(fs, &mut alloc, path)? };
-        // Q: what is the actually correct behaviour?
-        // E.g. if res is Ok but closing gives an error.
-        // Or if closing fails because something is broken and
-        // we'd already know that from an Err res.
-        let res = f(&mut file);
-        unsafe { file.close()? };
-        res
+        let file = CloseGuard(Some(unsafe { self.open(fs, alloc, path)? }));
+        // the file is closed in any case, an error of `f` is reported over one from closing
+        // as the latter likely follows from it
+        let res = f(file.0.as_ref().unwrap());
+        let closed = file.close();
+        let value = res?;
+        closed.map(|()| value)
     }
 
     pub fn new() -> Self {
@@ -978,6 +2581,10 @@ impl<S: driver::Storage> io::Seek for File<'_, '_, S>
 impl<S: driver::Storage> io::Write for File<'_, '_, S>
 {
     fn write(&self, buf: &[u8]) -> Result<usize> {
+        // littlefs only asserts this, and writes anyway without assertions
+        if self.alloc.borrow().state.flags & ll::lfs_open_flags_LFS_O_WRONLY == 0 {
+            return Err(io::Error::BadFileDescriptor);
+        }
         let return_code = unsafe { ll::lfs_file_write(
             &mut self.fs.alloc.borrow_mut().state,
             &mut self.alloc.borrow_mut().state,
@@ -990,7 +2597,43 @@ impl<S: driver::Storage> io::Write for File<'_, '_, S>
     fn flush(&self) -> Result<()> { Ok(()) }
 }
 
-#[derive(Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+#[cfg(feature = "std")]
+impl<S: driver::Storage> std::io::Read for &File<'_, '_, S>
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(File::read(self, buf)?)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: driver::Storage> std::io::Seek for &File<'_, '_, S>
+{
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        use core::convert::TryFrom;
+        let pos = match pos {
+            std::io::SeekFrom::Start(off) => u32::try_from(off).ok().map(io::SeekFrom::Start),
+            std::io::SeekFrom::End(off) => i32::try_from(off).ok().map(io::SeekFrom::End),
+            std::io::SeekFrom::Current(off) => i32::try_from(off).ok().map(io::SeekFrom::Current),
+        };
+        let pos = pos.ok_or(std::io::ErrorKind::InvalidInput)?;
+        Ok(File::seek(self, pos)? as u64)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: driver::Storage> std::io::Write for &File<'_, '_, S>
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(File::write(self, buf)?)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(self.sync()?)
+    }
+}
+
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DirEntry {
     file_name: PathBuf,
     metadata: Metadata,
@@ -1109,9 +2752,51 @@ impl<'a, 'b, S: driver::Storage> ReadDir<'a, 'b, S> {
     pub unsafe fn borrow_filesystem<'c>(&'c mut self) -> &'c Filesystem<'a, S> {
         &self.fs
     }
+
+    /// Open the file `name` in this directory, see `Filesystem::open_file_and_then`.
+    ///
+    /// littlefs has no way to open files relative to a directory, so this joins `name`
+    /// to the directory's path and resolves the result from the root, as usual.
+    #[cfg(feature = "dir-entry-path")]
+    pub fn open_file_at<R>(
+        &self,
+        name: &Path,
+        f: impl FnOnce(&File<'_, '_, S>) -> Result<R>,
+    ) -> Result<R> {
+        self.fs.open_file_and_then(&self.path.join(name), f)
+    }
+
+    /// Create or truncate the file `name` in this directory, see `open_file_at`.
+    #[cfg(feature = "dir-entry-path")]
+    pub fn create_file_at<R>(
+        &self,
+        name: &Path,
+        f: impl FnOnce(&File<'_, '_, S>) -> Result<R>,
+    ) -> Result<R> {
+        self.fs.create_file_and_then(&self.path.join(name), f)
+    }
 }
 
 impl<S: driver::Storage> ReadDir<'_, '_, S> {
+    /// The position in the directory, to return to with `seek`, also after reopening it.
+    fn tell(&self) -> Result<usize> {
+        let return_code = unsafe { ll::lfs_dir_tell(
+            &mut self.fs.alloc.borrow_mut().state,
+            &mut self.alloc.borrow_mut().state,
+        ) };
+        io::result_from(return_code as usize, return_code)
+    }
+
+    /// Continue reading at a position returned by `tell`.
+    fn seek(&mut self, position: usize) -> Result<()> {
+        let return_code = unsafe { ll::lfs_dir_seek(
+            &mut self.fs.alloc.borrow_mut().state,
+            &mut self.alloc.borrow_mut().state,
+            position as ll::lfs_off_t,
+        ) };
+        io::result_from((), return_code)
+    }
+
     // Again, not sure if this can be called twice
     // Update: This one seems to be safe to call multiple times,
     // it just goes through the "mlist" and removes itself.
@@ -1132,6 +2817,11 @@ impl<S: driver::Storage> ReadDir<'_, '_, S> {
 
 impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
 
+    /// Call `f` with an iterator over the entries of a directory.
+    ///
+    /// `ReadDir` is an `Iterator`, so entries can be selected with the usual adapters such
+    /// as `filter`. The directory is closed when `f` returns, whether or not it consumed
+    /// all entries.
     pub fn read_dir_and_then<R>(
         &self,
         path: &Path,
@@ -1147,6 +2837,31 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
         res
     }
 
+    /// Like `read_dir_and_then`, but only passes on entries whose file name matches the
+    /// glob `pattern`, see `Path::matches`. Errors are passed on as well.
+    pub fn read_dir_matching_and_then<R>(
+        &self,
+        path: &Path,
+        pattern: &str,
+        f: impl FnOnce(&mut dyn Iterator<Item = Result<DirEntry>>) -> Result<R>,
+    ) -> Result<R>
+    {
+        self.read_dir_and_then(path, |read_dir| {
+            f(&mut read_dir.filter(|entry| match entry {
+                Ok(entry) => entry.file_name().matches(pattern),
+                Err(_) => true,
+            }))
+        })
+    }
+
+    /// Collect the entries of a directory into a vector.
+    ///
+    /// Like `read_dir`, this includes the `.` and `..` entries.
+    #[cfg(feature = "alloc")]
+    pub fn read_dir_vec(&self, path: &Path) -> Result<alloc::vec::Vec<DirEntry>> {
+        self.read_dir_and_then(path, |read_dir| read_dir.collect())
+    }
+
 	/// Returns a pseudo-iterator over the entries within a directory.
     ///
     /// This is unsafe since it can induce UB just like File::open.
@@ -1189,16 +2904,69 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
         io::result_from(fs, return_code)
     }
 
+    /// Like `mount`, but returns `InvalidConfig` instead of asserting on the geometry
+    /// and the size limits, so misconfigured firmware can fail gracefully.
+    ///
+    /// `alloc` should come from `Allocation::try_new` to avoid its debug assertions.
+    pub fn try_mount(
+        alloc: &'a mut Allocation<Storage>,
+        storage: &'a mut Storage,
+    ) -> Result<Self> {
+        check_storage_requirements::<Storage>()?;
+        check_limits(alloc.config.name_max + 1, alloc.config.file_max, alloc.config.attr_max)?;
+        Self::mount(alloc, storage)
+    }
+
+    /// Like `mount`, with the given options. Returns `Invalid` for invalid options.
+    pub fn mount_with_options(
+        alloc: &'a mut Allocation<Storage>,
+        storage: &'a mut Storage,
+        options: MountOptions,
+    ) -> Result<Self> {
+        options.apply::<Storage>(&mut alloc.config)?;
+        Self::mount(alloc, storage)
+    }
+
+    /// Mount the filesystem, formatting the storage first if it does not hold one.
+    ///
+    /// Only `Corruption`, which littlefs reports for storage without a valid superblock,
+    /// leads to formatting; other errors, e.g. transient `Io` errors, are returned as is.
+    pub fn mount_or_format(
+        alloc: &'a mut Allocation<Storage>,
+        storage: &'a mut Storage,
+    ) -> Result<Self> {
+        let fs = Self::new(alloc, storage);
+        let mut alloc = fs.alloc.borrow_mut();
+        let mut return_code = unsafe { ll::lfs_mount(&mut alloc.state, &alloc.config) };
+        if return_code == ll::lfs_error_LFS_ERR_CORRUPT {
+            debug_now!("no filesystem found, formatting");
+            return_code = unsafe { ll::lfs_format(&mut alloc.state, &alloc.config) };
+            if return_code == 0 {
+                return_code = unsafe { ll::lfs_mount(&mut alloc.state, &alloc.config) };
+            }
+        }
+        drop(alloc);
+        io::result_from(fs, return_code)
+    }
+
     // Not public, user should use `mount`, possibly after `format`
     fn new(alloc: &'a mut Allocation<Storage>, storage: &'a mut Storage) -> Self {
 
         alloc.config.context = storage as *mut _ as *mut cty::c_void;
+        let block_count = storage.block_count();
+        debug_assert!(block_count > 0 && block_count <= Storage::BLOCK_COUNT);
+        alloc.config.block_count = block_count as _;
 
         alloc.config.read_buffer = &mut alloc.cache.read as *mut _ as *mut cty::c_void;
         alloc.config.prog_buffer = &mut alloc.cache.write as *mut _ as *mut cty::c_void;
         alloc.config.lookahead_buffer = &mut alloc.cache.lookahead as *mut _ as *mut cty::c_void;
 
-        Filesystem { alloc: RefCell::new(alloc), storage }
+        Filesystem {
+            alloc: RefCell::new(alloc),
+            storage,
+            open_files: Cell::new(0),
+            writers: Cell::new(ptr::null_mut()),
+        }
     }
 
     /// Deconstruct `Filesystem`, intention is to allow access to
@@ -1209,6 +2977,29 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
         (self.alloc.into_inner(), self.storage)
     }
 
+    /// Unmount the filesystem, releasing the borrows of allocation and storage.
+    ///
+    /// littlefs commits metadata as part of each operation, so there is nothing
+    /// pending here, but open files must be closed (and hence synced) before.
+    /// To use the filesystem again, `mount` it anew.
+    pub fn unmount(self) -> Result<()> {
+        debug_assert_eq!(self.open_file_count(), 0, "unmounting with open files");
+        let return_code = unsafe { ll::lfs_unmount(&mut self.alloc.borrow_mut().state) };
+        io::result_from((), return_code)
+    }
+
+    /// Unmount and mount again, e.g. after the storage was modified through
+    /// `borrow_storage_mut`, so that littlefs reads its state afresh.
+    ///
+    /// As for `unmount`, there is nothing to flush, taking `self` ensures that no files
+    /// are open. If mounting fails, the borrows of allocation and storage are released.
+    pub fn remount(self) -> Result<Self> {
+        let return_code = unsafe { ll::lfs_unmount(&mut self.alloc.borrow_mut().state) };
+        io::result_from((), return_code)?;
+        let (alloc, storage) = self.into_inner();
+        Self::mount(alloc, storage)
+    }
+
     /// Creates a new, empty directory at the provided path.
     pub fn create_dir(&self, path: &Path) -> Result<()> {
 
@@ -1286,10 +3077,39 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
         Ok(contents)
     }
 
+    /// Read the beginning of a file into `buf`, returning the number of bytes read.
+    ///
+    /// Reads at most `buf.len()` bytes; if the file is longer, `buf` is filled and the
+    /// rest of the file is ignored.
+    pub fn read_into(&self, path: &Path, buf: &mut [u8]) -> Result<usize> {
+        File::open_and_then(self, path, |file| file.read(buf))
+    }
+
+    /// Read the beginning of a file into a fixed-size buffer.
+    ///
+    /// Returns the buffer together with the number of bytes read, as for `read_into`.
+    pub fn read_chunk<N: generic_array::ArrayLength<u8>>(
+        &self,
+        path: &Path,
+    ) -> Result<(Bytes<N>, usize)> {
+        let mut contents: Bytes<N> = Default::default();
+        let len = self.read_into(path, &mut contents)?;
+        Ok((contents, len))
+    }
+
+    /// The first `N` bytes of a file, zero-padded if it is shorter, e.g. to check a magic number.
+    pub fn read_header<N: generic_array::ArrayLength<u8>>(&self, path: &Path) -> Result<Bytes<N>> {
+        self.read_chunk(path).map(|(header, _)| header)
+    }
+
     /// Write a slice as the entire contents of a file.
     ///
     /// This function will create a file if it does not exist,
     /// and will entirely replace its contents if it does.
+    ///
+    /// The file is synced before returning. If writing fails, for instance with `NoSpace`,
+    /// a file created by this call is removed, while a file that existed before keeps its
+    /// previous contents: littlefs does not commit a file after a failed write.
     pub fn write(
         &self,
         path: &Path,
@@ -1298,13 +3118,156 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
     {
         #[cfg(test)]
         println!("writing {:?}", path);
-        File::create_and_then(self, path, |file| {
+        let existed = path.exists(self);
+        let mut created = false;
+        let result = File::create_and_then(self, path, |file| {
             use io::Write;
-            file.write_all(contents)
+            created = !existed;
+            file.write_all(contents)?;
+            file.sync()
+        });
+        if result.is_err() && created {
+            self.remove(path).ok();
+        }
+        result
+    }
+
+    /// Append a slice to the end of a file, creating it if it does not exist.
+    ///
+    /// The file is opened in append mode, so the data ends up at the end of the file
+    /// regardless of the position of other handles. It is synced before returning.
+    pub fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(true)
+            .open_and_then(self, path, |file| {
+                use io::Write;
+                file.write_all(contents)?;
+                file.sync()
+            })
+    }
+
+    /// Compute the CRC-32 of a file's contents and store it as an attribute,
+    /// with id `consts::CONTENT_HASH_ATTRIBUTE_ID`.
+    ///
+    /// The hash is not updated automatically, call this again after modifying the file.
+    pub fn set_content_hash(&self, path: &Path) -> Result<()> {
+        let crc = File::open_and_then(self, path, |file| {
+            let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+            let mut crc = crc::Crc32::new();
+            loop {
+                let read = file.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                crc.update(&buf[..read]);
+            }
+            Ok(crc.finalize())
         })?;
-        Ok(())
+
+        let mut attribute = Attribute::new(crate::consts::CONTENT_HASH_ATTRIBUTE_ID);
+        attribute.set_data(&crc.to_le_bytes());
+        self.set_attribute(path, &attribute)
+    }
+
+    /// Read the entire contents of a file into `buf`, verifying them against the
+    /// hash stored by [`set_content_hash`](struct.Filesystem.html#method.set_content_hash).
+    ///
+    /// Returns the number of bytes read, `NoAttribute` if the file was never hashed,
+    /// `FileTooBig` if it does not fit in `buf`, and `Corruption` on a hash mismatch.
+    pub fn read_verified_by_attr(&self, path: &Path, buf: &mut [u8]) -> Result<usize> {
+        let expected = match self.attribute(path, crate::consts::CONTENT_HASH_ATTRIBUTE_ID)? {
+            Some(attribute) if attribute.data().len() == 4 => {
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(attribute.data());
+                u32::from_le_bytes(bytes)
+            }
+            Some(_) => return Err(io::Error::Corruption),
+            None => return Err(io::Error::NoAttribute),
+        };
+
+        let read = File::open_and_then(self, path, |file| {
+            if file.len()? > buf.len() {
+                return Err(io::Error::FileTooBig);
+            }
+            file.read(buf)
+        })?;
+
+        if crc::crc32(&buf[..read]) != expected {
+            return Err(io::Error::Corruption);
+        }
+        Ok(read)
+    }
+
+}
+
+/// A mounted filesystem that can be shared between threads, e.g. in an `Arc`.
+///
+/// littlefs itself is single-threaded, so all access is serialized by a mutex.
+/// A panic during an operation, e.g. in the closure passed to `with`, may leave files
+/// open; later operations then panic too.
+#[cfg(feature = "std")]
+pub struct SyncFilesystem<'a, Storage: driver::Storage> {
+    fs: std::sync::Mutex<Filesystem<'a, Storage>>,
+}
+
+// NOTE(unsafe) the filesystem state only points into the allocation and storage it
+// borrows exclusively, and the mutex ensures one thread at a time uses it.
+#[cfg(feature = "std")]
+unsafe impl<Storage: driver::Storage + Send> Send for SyncFilesystem<'_, Storage> {}
+#[cfg(feature = "std")]
+unsafe impl<Storage: driver::Storage + Send> Sync for SyncFilesystem<'_, Storage> {}
+
+#[cfg(feature = "std")]
+impl<'a, Storage: driver::Storage> SyncFilesystem<'a, Storage> {
+    pub fn new(fs: Filesystem<'a, Storage>) -> Self {
+        Self { fs: std::sync::Mutex::new(fs) }
+    }
+
+    /// Run `f` with exclusive access to the filesystem.
+    pub fn with<R>(&self, f: impl FnOnce(&Filesystem<'a, Storage>) -> R) -> R {
+        let fs = self.fs.lock().expect("filesystem used after a panic");
+        f(&fs)
+    }
+
+    pub fn into_inner(self) -> Filesystem<'a, Storage> {
+        self.fs.into_inner().expect("filesystem used after a panic")
+    }
+
+    /// Read the entire contents of a file.
+    pub fn read(&self, path: &Path) -> Result<std::vec::Vec<u8>> {
+        self.with(|fs| fs.open_file_and_then(path, |file| {
+            let mut contents = std::vec![0; file.len()?];
+            let read = file.read(&mut contents)?;
+            contents.truncate(read);
+            Ok(contents)
+        }))
+    }
+
+    pub fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.with(|fs| fs.write(path, contents))
+    }
+
+    pub fn metadata(&self, path: &Path) -> Result<Metadata> {
+        self.with(|fs| fs.metadata(path))
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        self.with(|fs| fs.exists(path))
+    }
+
+    pub fn create_dir(&self, path: &Path) -> Result<()> {
+        self.with(|fs| fs.create_dir(path))
+    }
+
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        self.with(|fs| fs.remove(path))
     }
 
+    pub fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.with(|fs| fs.rename(from, to))
+    }
 }
 
 #[cfg(test)]
@@ -1314,7 +3277,7 @@ mod tests {
     use generic_array::typenum::consts;
     use driver::Storage as LfsStorage;
     use io::Result as LfsResult;
-    const_ram_storage!(TestStorage, 4096);
+    const_ram_storage!(TestStorage, 4096, access_counters);
 
     #[test]
     fn todo() {
@@ -1463,6 +3426,207 @@ mod tests {
         }).unwrap();
     }
 
+    #[test]
+    fn lookahead_buffer_size() {
+        let alloc = Allocation::<TestStorage>::new();
+        assert_eq!(mem::size_of_val(&alloc.cache.lookahead), alloc.config.lookahead_size as usize);
+    }
+
+    #[test]
+    fn io_sizes() {
+        let mut test_storage = TestStorage::new();
+        Filesystem::format(&mut test_storage).unwrap();
+        Filesystem::mount_and_then(&mut test_storage, |fs| {
+            assert_eq!(fs.io_sizes(), (16, 512, 512));
+            Ok(())
+        }).unwrap();
+    }
+
+    #[test]
+    fn small_writes_are_cached() {
+        let mut test_storage = TestStorage::new();
+        Filesystem::format(&mut test_storage).unwrap();
+        test_storage.reset_counts();
+        Filesystem::mount_and_then(&mut test_storage, |fs| {
+            fs.create_file_and_then(b"stream.bin\0".try_into().unwrap(), |file| {
+                for chunk in [0x5a; 2048].chunks(16) {
+                    file.write(chunk)?;
+                }
+                Ok(())
+            })
+        }).unwrap();
+        // 128 writes of 16 bytes, programmed in units of the 512 byte cache
+        assert!(test_storage.write_count() < 16);
+    }
+
+    #[test]
+    fn limits() {
+        let mut test_storage = TestStorage::new();
+        Filesystem::format(&mut test_storage).unwrap();
+        Filesystem::mount_and_then(&mut test_storage, |fs| {
+            let limits = fs.limits();
+            assert_eq!(limits.name_max, 255);
+            assert_eq!(limits.file_max, 2_147_483_647);
+            assert_eq!(limits.attr_max, 1_022);
+            Ok(())
+        }).unwrap();
+    }
+
+    #[test]
+    fn access_counters() {
+        let mut test_storage = TestStorage::new();
+        Filesystem::format(&mut test_storage).unwrap();
+        Filesystem::mount_and_then(&mut test_storage, |fs| {
+            fs.write(b"small.txt\0".try_into().unwrap(), b"counted")
+        }).unwrap();
+
+        assert!(test_storage.read_count() > 0);
+        assert!(test_storage.write_count() > 0);
+        // superblock pair, at most a few compactions
+        assert!(test_storage.erase_count() >= 2);
+        assert!(test_storage.erase_count() <= 2 * TestStorage::BLOCK_COUNT);
+
+        test_storage.reset_counts();
+        assert_eq!(test_storage.erase_count(), 0);
+    }
+
+    #[test]
+    fn small_reads_hit_the_cache() {
+        let filename = b"records.bin\0".try_into().unwrap();
+        let mut test_storage = TestStorage::new();
+        Filesystem::format(&mut test_storage).unwrap();
+        Filesystem::mount_and_then(&mut test_storage, |fs| {
+            fs.write(filename, &[0x42; 1024])
+        }).unwrap();
+
+        let mut reads_for_record_size = |record_size: usize| {
+            test_storage.reset_counts();
+            Filesystem::mount_and_then(&mut test_storage, |fs| {
+                fs.open_file_and_then(filename, |file| {
+                    let mut record = [0u8; 256];
+                    while file.read(&mut record[..record_size])? == record_size {}
+                    Ok(())
+                })
+            }).unwrap();
+            test_storage.read_count()
+        };
+
+        let small = reads_for_record_size(4);
+        assert_eq!(small, reads_for_record_size(256));
+        assert!(small < 1024 / 4);
+    }
+
+    #[test]
+    fn misaligned_storage_access() {
+        let mut test_storage = TestStorage::new();
+        let mut alloc: Allocation<TestStorage> = Allocation::new();
+        alloc.config.context = &mut test_storage as *mut TestStorage as *mut cty::c_void;
+        let config: *const ll::lfs_config = &alloc.config;
+        let mut buf = [0u8; 512];
+        let buffer = buf.as_mut_ptr() as *mut cty::c_void;
+
+        let read = Filesystem::<TestStorage>::lfs_config_read;
+        assert_eq!(read(config, 0, 16, buffer, 32), 0);
+        assert_eq!(read(config, 0, 8, buffer, 16), io::Error::Invalid.code());
+        assert_eq!(read(config, 0, 0, buffer, 12), io::Error::Invalid.code());
+
+        let prog = Filesystem::<TestStorage>::lfs_config_prog;
+        assert_eq!(prog(config, 1, 0, buffer, 512), 0);
+        assert_eq!(prog(config, 1, 16, buffer, 512), io::Error::Invalid.code());
+        assert_eq!(prog(config, 1, 0, buffer, 256), io::Error::Invalid.code());
+        assert_eq!(test_storage.write_count(), 1);
+    }
+
+    #[test]
+    fn callbacks_use_configured_block_size() {
+        let mut test_storage = TestStorage::new();
+        let mut alloc: Allocation<TestStorage> = Allocation::new();
+        assert_eq!(alloc.config.block_size as usize, TestStorage::BLOCK_SIZE);
+        alloc.config.context = &mut test_storage as *mut TestStorage as *mut cty::c_void;
+        alloc.config.block_size = 1024;
+        let config: *const ll::lfs_config = &alloc.config;
+
+        let mut buf = [0x42u8; 512];
+        let buffer = buf.as_mut_ptr() as *mut cty::c_void;
+        assert_eq!(Filesystem::<TestStorage>::lfs_config_prog(config, 1, 512, buffer, 512), 0);
+        let image = test_storage.snapshot();
+        assert!(image[1536..2048].iter().all(|&byte| byte == 0x42));
+        assert!(image[..1536].iter().all(|&byte| byte != 0x42));
+
+        buf = [0; 512];
+        let buffer = buf.as_mut_ptr() as *mut cty::c_void;
+        assert_eq!(Filesystem::<TestStorage>::lfs_config_read(config, 1, 512, buffer, 512), 0);
+        assert_eq!(buf, [0x42; 512]);
+
+        assert_eq!(Filesystem::<TestStorage>::lfs_config_erase(config, 1), 0);
+        let image = test_storage.snapshot();
+        assert!(image[1024..2048].iter().all(|&byte| byte != 0x42));
+    }
+
+    #[test]
+    fn file_block_count() {
+        let mut test_storage = TestStorage::new();
+        Filesystem::format(&mut test_storage).unwrap();
+        Filesystem::mount_and_then(&mut test_storage, |fs| {
+            let small = b"small\0".try_into().unwrap();
+            fs.write(small, b"inlined")?;
+            assert_eq!(fs.file_block_count(small)?, 0);
+
+            let large = b"large\0".try_into().unwrap();
+            fs.write(large, &[0x2a; 2378])?;
+            let blocks = fs.file_block_count(large)?;
+            // 2378 bytes at up to 504 bytes of data per 512 byte block
+            assert_eq!(blocks, 5);
+            assert!(blocks <= fs.total_blocks() - fs.available_blocks()?);
+            Ok(())
+        }).unwrap();
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let path = b"kept.txt\0".try_into().unwrap();
+        let mut test_storage = TestStorage::new();
+        Filesystem::format(&mut test_storage).unwrap();
+        Filesystem::mount_and_then(&mut test_storage, |fs| fs.write(path, b"keep me")).unwrap();
+
+        let snapshot = test_storage.snapshot();
+        Filesystem::mount_and_then(&mut test_storage, |fs| fs.remove(path)).unwrap();
+        Filesystem::mount_and_then(&mut test_storage, |fs| {
+            assert!(!fs.exists(path));
+            Ok(())
+        }).unwrap();
+
+        test_storage.restore(&snapshot);
+        Filesystem::mount_and_then(&mut test_storage, |fs| {
+            let contents: heapless::Vec<_, 16> = fs.read(path)?;
+            assert_eq!(contents, b"keep me");
+            Ok(())
+        }).unwrap();
+    }
+
+    #[test]
+    fn image_round_trip() {
+        let path = b"carried.txt\0".try_into().unwrap();
+        let mut device = TestStorage::new();
+        Filesystem::format(&mut device).unwrap();
+        Filesystem::mount_and_then(&mut device, |fs| fs.write(path, b"from the device")).unwrap();
+        let image = std::vec::Vec::from(device.to_image());
+
+        let mut host = TestStorage::new();
+        assert_eq!(host.from_image(&image[1..]), Err(io::Error::Invalid));
+        host.from_image(&image).unwrap();
+        Filesystem::mount_and_then(&mut host, |fs| {
+            let contents: heapless::Vec<_, 16> = fs.read(path)?;
+            assert_eq!(contents, b"from the device");
+            Ok(())
+        }).unwrap();
+    }
+
+    #[test]
+    fn storage_conformance() {
+        crate::testing::run_storage_conformance(TestStorage::new).unwrap();
+    }
+
     #[test]
     fn path() {
         let _path: &Path = b"a.txt\0".try_into().unwrap();
@@ -97,6 +97,7 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 /// Definition of errors that might be returned by filesystem functionality.
 #[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// Error code was >=0, operation was successful.
     Success,
@@ -128,13 +129,30 @@ pub enum Error {
     NoAttribute,
     /// Filename too long
     FilenameTooLong,
+    /// Directories nested deeper than `consts::MAX_RECURSION_DEPTH`.
+    /// Not a littlefs error, its `code` is that of `FilenameTooLong`.
+    DirectoryTooDeep,
+    /// The file is already open for writing through another handle.
+    /// Not a littlefs error, its `code` is that of `Invalid`.
+    FileBusy,
+    /// `Storage::MAX_OPEN_FILES` files are open already.
+    /// Not a littlefs error, its `code` is that of `NoMemory`.
+    TooManyOpenFiles,
+    /// The storage geometry does not meet the requirements of littlefs.
+    /// Not a littlefs error, its `code` is that of `Invalid`.
+    InvalidConfig(crate::fs::ConfigError),
     /// Unknown error occurred, integer code specified.
     Unknown(i32),
 }
 
 impl From<crate::path::Error> for Error {
-    fn from(_error: crate::path::Error) -> Self {
-        Error::Io
+    fn from(error: crate::path::Error) -> Self {
+        match error {
+            // `consts::PATH_MAX` is below littlefs' name limit, so this also covers
+            // file names that are too long
+            crate::path::Error::TooLarge => Error::FilenameTooLong,
+            _ => Error::Io,
+        }
     }
 }
 
@@ -163,6 +181,56 @@ impl From<i32> for Error {
     }
 }
 
+impl From<crate::fs::ConfigError> for Error {
+    fn from(error: crate::fs::ConfigError) -> Self {
+        Error::InvalidConfig(error)
+    }
+}
+
+impl Error {
+    /// The littlefs error code this error was created from (negative, or zero for `Success`).
+    ///
+    /// Errors raised by this crate rather than littlefs, such as `DirectoryTooDeep`, have
+    /// no code of their own and report the closest littlefs one. The mapping is lossy:
+    /// `Error::from(error.code())` gives back the littlefs error, not the original one.
+    pub fn code(&self) -> i32 {
+        match *self {
+            Error::Success => 0,
+            Error::Io => ll::lfs_error_LFS_ERR_IO,
+            Error::Corruption => ll::lfs_error_LFS_ERR_CORRUPT,
+            Error::NoSuchEntry => ll::lfs_error_LFS_ERR_NOENT,
+            Error::EntryAlreadyExisted => ll::lfs_error_LFS_ERR_EXIST,
+            Error::PathNotDir => ll::lfs_error_LFS_ERR_NOTDIR,
+            Error::PathIsDir => ll::lfs_error_LFS_ERR_ISDIR,
+            Error::DirNotEmpty => ll::lfs_error_LFS_ERR_NOTEMPTY,
+            Error::BadFileDescriptor => ll::lfs_error_LFS_ERR_BADF,
+            Error::FileTooBig => ll::lfs_error_LFS_ERR_FBIG,
+            Error::Invalid | Error::FileBusy | Error::InvalidConfig(_) => ll::lfs_error_LFS_ERR_INVAL,
+            Error::NoSpace => ll::lfs_error_LFS_ERR_NOSPC,
+            Error::NoMemory | Error::TooManyOpenFiles => ll::lfs_error_LFS_ERR_NOMEM,
+            Error::NoAttribute => ll::lfs_error_LFS_ERR_NOATTR,
+            Error::FilenameTooLong | Error::DirectoryTooDeep => ll::lfs_error_LFS_ERR_NAMETOOLONG,
+            Error::Unknown(code) => code,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        use std::io::ErrorKind;
+        let kind = match error {
+            Error::NoSuchEntry => ErrorKind::NotFound,
+            Error::EntryAlreadyExisted => ErrorKind::AlreadyExists,
+            Error::Invalid => ErrorKind::InvalidInput,
+            Error::Corruption => ErrorKind::InvalidData,
+            Error::NoMemory => ErrorKind::OutOfMemory,
+            _ => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, std::format!("littlefs: {:?}", error))
+    }
+}
+
 pub fn result_from<T>(return_value: T, error_code: ll::lfs_error) -> Result<T> {
     let error: Error = error_code.into();
     match error {
@@ -170,3 +238,48 @@ pub fn result_from<T>(return_value: T, error_code: ll::lfs_error) -> Result<T> {
         _ => Err(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ll, Error};
+
+    #[test]
+    fn error_codes() {
+        let table = [
+            (ll::lfs_error_LFS_ERR_IO, Error::Io),
+            (ll::lfs_error_LFS_ERR_CORRUPT, Error::Corruption),
+            (ll::lfs_error_LFS_ERR_NOENT, Error::NoSuchEntry),
+            (ll::lfs_error_LFS_ERR_EXIST, Error::EntryAlreadyExisted),
+            (ll::lfs_error_LFS_ERR_NOTDIR, Error::PathNotDir),
+            (ll::lfs_error_LFS_ERR_ISDIR, Error::PathIsDir),
+            (ll::lfs_error_LFS_ERR_NOTEMPTY, Error::DirNotEmpty),
+            (ll::lfs_error_LFS_ERR_BADF, Error::BadFileDescriptor),
+            (ll::lfs_error_LFS_ERR_FBIG, Error::FileTooBig),
+            (ll::lfs_error_LFS_ERR_INVAL, Error::Invalid),
+            (ll::lfs_error_LFS_ERR_NOSPC, Error::NoSpace),
+            (ll::lfs_error_LFS_ERR_NOMEM, Error::NoMemory),
+            (ll::lfs_error_LFS_ERR_NOATTR, Error::NoAttribute),
+            (ll::lfs_error_LFS_ERR_NAMETOOLONG, Error::FilenameTooLong),
+        ];
+        for (i, (code, error)) in table.iter().enumerate() {
+            assert!(*code < 0);
+            assert_eq!(Error::from(*code), *error);
+            assert_eq!(error.code(), *code);
+            // every code has a variant of its own
+            assert!(table[..i].iter().all(|(_, other)| other != error));
+        }
+
+        assert_eq!(Error::from(0), Error::Success);
+        assert_eq!(Error::Success.code(), 0);
+        assert_eq!(Error::from(-1000), Error::Unknown(-1000));
+        assert_eq!(Error::Unknown(-1000).code(), -1000);
+
+        // errors of this crate map to littlefs codes, but not back
+        assert_eq!(Error::DirectoryTooDeep.code(), ll::lfs_error_LFS_ERR_NAMETOOLONG);
+        assert_eq!(Error::from(Error::DirectoryTooDeep.code()), Error::FilenameTooLong);
+        assert_eq!(Error::from(Error::FileBusy.code()), Error::Invalid);
+        assert_eq!(Error::from(Error::TooManyOpenFiles.code()), Error::NoMemory);
+        let config = Error::InvalidConfig(crate::fs::ConfigError::BlockTooSmall);
+        assert_eq!(Error::from(config.code()), Error::Invalid);
+    }
+}
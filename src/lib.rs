@@ -1,4 +1,4 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
 /*!
 
@@ -126,6 +126,9 @@ assert_eq!(&buf, b"black smoke");
 /// Low-level bindings
 pub use littlefs2_sys as ll;
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 #[macro_use]
 extern crate delog;
 generate_macros!();
@@ -136,6 +139,7 @@ pub mod macros;
 
 #[cfg(feature = "c-stubs")]
 mod c_stubs;
+mod crc;
 
 pub mod consts;
 pub mod driver;
@@ -144,6 +148,9 @@ pub mod fs;
 pub mod io;
 pub mod path;
 
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 /// get information about the C backend
 pub fn version() -> Version {
     Version {
@@ -135,6 +135,11 @@ macro_rules! ram_storage { (
     };
 }
 
+/// An implementation of the Storage trait in a fixed-size array, constructible in `const`.
+///
+/// With `access_counters=true` (or the short form `$Name, $bytes, access_counters`), the
+/// storage counts its reads, writes and erases. The read counter is a `Cell`, which makes
+/// the storage `!Sync`, so it cannot be put in a `static` with the counters enabled.
 #[macro_export]
 macro_rules! const_ram_storage { (
 
@@ -150,25 +155,48 @@ macro_rules! const_ram_storage { (
     filename_max_plus_one_ty=$filename_max_plus_one:path,
     path_max_plus_one_ty=$path_max_plus_one:path,
     result=$Result:ident,
+    access_counters=$access_counters:tt,
 
 ) => {
-        pub struct $Name {
-            buf: [u8; $block_size * $block_count],
-        }
+        const_ram_storage!(@struct $Name, $block_size * $block_count, $erase_value, $access_counters);
 
         impl $Name {
             const ERASE_VALUE: u8 = $erase_value;
-            pub const fn new() -> Self {
-                // Self::default()
-                Self { buf: [$erase_value; $block_size * $block_count] }
+
+            /// A copy of the storage contents, to `restore` later.
+            pub fn snapshot(&self) -> [u8; $block_size * $block_count] {
+                self.buf
+            }
+
+            /// Overwrite the storage contents with a `snapshot`.
+            ///
+            /// Panics if `image` has the wrong length. Remount afterwards, mounted
+            /// filesystems do not notice the change.
+            pub fn restore(&mut self, image: &[u8]) {
+                self.buf.copy_from_slice(image);
+            }
+
+            /// The storage contents, e.g. to write them to a file on the host.
+            pub fn to_image(&self) -> &[u8] {
+                &self.buf
+            }
+
+            /// Overwrite the storage contents with an image of the same geometry.
+            ///
+            /// Fails with `Invalid` unless `image` is exactly as large as the storage.
+            #[allow(clippy::wrong_self_convention)]
+            pub fn from_image(&mut self, image: &[u8]) -> $Result<()> {
+                if image.len() != self.buf.len() {
+                    return Err($crate::io::Error::Invalid);
+                }
+                self.buf.copy_from_slice(image);
+                Ok(())
             }
         }
 
         impl Default for $Name {
             fn default() -> Self {
-                Self {
-                    buf: [$erase_value; $block_size * $block_count],
-                }
+                Self::new()
             }
         }
 
@@ -184,6 +212,7 @@ macro_rules! const_ram_storage { (
                 let read_size: usize = Self::READ_SIZE;
                 debug_assert!(offset % read_size == 0);
                 debug_assert!(buf.len() % read_size == 0);
+                self.count_read();
                 for (from, to) in self.buf[offset..].iter().zip(buf.iter_mut()) {
                     *to = *from;
                 }
@@ -194,6 +223,7 @@ macro_rules! const_ram_storage { (
                 let write_size: usize = Self::WRITE_SIZE;
                 debug_assert!(offset % write_size == 0);
                 debug_assert!(data.len() % write_size == 0);
+                self.count_write();
                 for (from, to) in data.iter().zip(self.buf[offset..].iter_mut()) {
                     *to = *from;
                 }
@@ -204,6 +234,7 @@ macro_rules! const_ram_storage { (
                 let block_size: usize = Self::BLOCK_SIZE;
                 debug_assert!(offset % block_size == 0);
                 debug_assert!(len % block_size == 0);
+                self.count_erase();
                 for byte in self.buf[offset..offset + len].iter_mut() {
                     *byte = Self::ERASE_VALUE;
                 }
@@ -211,6 +242,125 @@ macro_rules! const_ram_storage { (
             }
         }
     };
+    (
+
+    name=$Name:ident,
+    trait=$StorageTrait:path,
+    erase_value=$erase_value:expr,
+    read_size=$read_size:expr,
+    write_size=$write_size:expr,
+    cache_size_ty=$cache_size:path,
+    block_size=$block_size:expr,
+    block_count=$block_count:expr,
+    lookaheadwords_size_ty=$lookaheadwords_size:path,
+    filename_max_plus_one_ty=$filename_max_plus_one:path,
+    path_max_plus_one_ty=$path_max_plus_one:path,
+    result=$Result:ident,
+
+) => {
+        const_ram_storage!(
+            name=$Name,
+            trait=$StorageTrait,
+            erase_value=$erase_value,
+            read_size=$read_size,
+            write_size=$write_size,
+            cache_size_ty=$cache_size,
+            block_size=$block_size,
+            block_count=$block_count,
+            lookaheadwords_size_ty=$lookaheadwords_size,
+            filename_max_plus_one_ty=$filename_max_plus_one,
+            path_max_plus_one_ty=$path_max_plus_one,
+            result=$Result,
+            access_counters=false,
+        );
+    };
+    (@struct $Name:ident, $len:expr, $erase_value:expr, false) => {
+        pub struct $Name {
+            buf: [u8; $len],
+        }
+
+        impl $Name {
+            pub const fn new() -> Self {
+                Self {
+                    buf: [$erase_value; $len],
+                }
+            }
+
+            fn count_read(&self) {}
+            fn count_write(&mut self) {}
+            fn count_erase(&mut self) {}
+        }
+    };
+    (@struct $Name:ident, $len:expr, $erase_value:expr, true) => {
+        pub struct $Name {
+            buf: [u8; $len],
+            read_count: core::cell::Cell<usize>,
+            write_count: usize,
+            erase_count: usize,
+        }
+
+        impl $Name {
+            pub const fn new() -> Self {
+                Self {
+                    buf: [$erase_value; $len],
+                    read_count: core::cell::Cell::new(0),
+                    write_count: 0,
+                    erase_count: 0,
+                }
+            }
+
+            /// Number of calls to `read` so far, for wear and performance analysis.
+            pub fn read_count(&self) -> usize {
+                self.read_count.get()
+            }
+
+            /// Number of calls to `write` so far.
+            pub fn write_count(&self) -> usize {
+                self.write_count
+            }
+
+            /// Number of calls to `erase` so far.
+            pub fn erase_count(&self) -> usize {
+                self.erase_count
+            }
+
+            /// Set all access counters back to zero.
+            pub fn reset_counts(&mut self) {
+                self.read_count.set(0);
+                self.write_count = 0;
+                self.erase_count = 0;
+            }
+
+            fn count_read(&self) {
+                self.read_count.set(self.read_count.get() + 1);
+            }
+
+            fn count_write(&mut self) {
+                self.write_count += 1;
+            }
+
+            fn count_erase(&mut self) {
+                self.erase_count += 1;
+            }
+        }
+    };
+    ($Name:ident, $bytes:expr, access_counters) => {
+        const_ram_storage!(
+            name=$Name,
+            trait=LfsStorage,
+            erase_value=0xff,
+            read_size=16,
+            write_size=512,
+            cache_size_ty=$crate::consts::U512,
+            block_size=512,
+            block_count=$bytes/512,
+            lookaheadwords_size_ty=$crate::consts::U1,
+            filename_max_plus_one_ty=$crate::consts::U256,
+            path_max_plus_one_ty=$crate::consts::U256,
+            result=LfsResult,
+            access_counters=true,
+        );
+    };
     ($Name:ident, $bytes:expr) => {
         const_ram_storage!(
             name=$Name,
@@ -225,6 +375,191 @@ macro_rules! const_ram_storage { (
             filename_max_plus_one_ty=$crate::consts::U256,
             path_max_plus_one_ty=$crate::consts::U256,
             result=LfsResult,
+            access_counters=false,
         );
     };
 }
+
+/// Like `const_ram_storage!`, but with the buffer on the heap, for large or many geometries.
+///
+/// The block count is chosen at runtime, so tests can sweep over sizes without
+/// overflowing the stack: `$Name::new(64)`. The generated storage reports it through
+/// `Storage::block_count`, its `BLOCK_COUNT` is only an upper bound.
+///
+/// Requires the `alloc` feature, and `extern crate alloc` in the invoking crate.
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! vec_ram_storage { (
+
+    name=$Name:ident,
+    trait=$StorageTrait:path,
+    erase_value=$erase_value:expr,
+    read_size=$read_size:expr,
+    write_size=$write_size:expr,
+    cache_size_ty=$cache_size:path,
+    block_size=$block_size:expr,
+    lookaheadwords_size_ty=$lookaheadwords_size:path,
+    filename_max_plus_one_ty=$filename_max_plus_one:path,
+    path_max_plus_one_ty=$path_max_plus_one:path,
+    result=$Result:ident,
+
+) => {
+        pub struct $Name {
+            buf: ::alloc::vec::Vec<u8>,
+        }
+
+        impl $Name {
+            const ERASE_VALUE: u8 = $erase_value;
+            const GEOMETRY: () = $crate::fs::assert_storage_requirements::<Self>();
+
+            /// Panics unless `blocks` is between 1 and `BLOCK_COUNT`.
+            pub fn new(blocks: usize) -> Self {
+                // fails to compile for an invalid geometry
+                #[allow(clippy::let_unit_value)]
+                let () = Self::GEOMETRY;
+                assert!(blocks > 0 && blocks <= <Self as $StorageTrait>::BLOCK_COUNT);
+                Self {
+                    buf: ::alloc::vec![$erase_value; $block_size * blocks],
+                }
+            }
+        }
+
+        impl $StorageTrait for $Name {
+            const READ_SIZE: usize = $read_size;
+            const WRITE_SIZE: usize = $write_size;
+            type CACHE_SIZE = $cache_size;
+            const BLOCK_SIZE: usize = $block_size;
+            // littlefs counts blocks in 32 bits
+            const BLOCK_COUNT: usize = u32::MAX as usize;
+            type LOOKAHEADWORDS_SIZE = $lookaheadwords_size;
+
+            fn block_count(&self) -> usize {
+                self.buf.len() / $block_size
+            }
+
+            fn read(&self, offset: usize, buf: &mut [u8]) -> $Result<usize> {
+                let read_size: usize = Self::READ_SIZE;
+                debug_assert!(offset % read_size == 0);
+                debug_assert!(buf.len() % read_size == 0);
+                for (from, to) in self.buf[offset..].iter().zip(buf.iter_mut()) {
+                    *to = *from;
+                }
+                Ok(buf.len())
+            }
+
+            fn write(&mut self, offset: usize, data: &[u8]) -> $Result<usize> {
+                let write_size: usize = Self::WRITE_SIZE;
+                debug_assert!(offset % write_size == 0);
+                debug_assert!(data.len() % write_size == 0);
+                for (from, to) in data.iter().zip(self.buf[offset..].iter_mut()) {
+                    *to = *from;
+                }
+                Ok(data.len())
+            }
+
+            fn erase(&mut self, offset: usize, len: usize) -> $Result<usize> {
+                let block_size: usize = Self::BLOCK_SIZE;
+                debug_assert!(offset % block_size == 0);
+                debug_assert!(len % block_size == 0);
+                for byte in self.buf[offset..offset + len].iter_mut() {
+                    *byte = Self::ERASE_VALUE;
+                }
+                Ok(len)
+            }
+        }
+    };
+}
+
+/// An implementation of the Storage trait backed by a `std::fs::File`, e.g. a littlefs image.
+///
+/// The geometry is given like for `ram_storage!`, and has to match the image.
+/// Erasing writes `erase_value` across the erased blocks.
+///
+/// Requires the `std` feature.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! file_storage { (
+
+    name=$Name:ident,
+    trait=$StorageTrait:path,
+    erase_value=$erase_value:expr,
+    read_size=$read_size:expr,
+    write_size=$write_size:expr,
+    cache_size_ty=$cache_size:path,
+    block_size=$block_size:expr,
+    block_count=$block_count:expr,
+    lookaheadwords_size_ty=$lookaheadwords_size:path,
+    filename_max_plus_one_ty=$filename_max_plus_one:path,
+    path_max_plus_one_ty=$path_max_plus_one:path,
+    result=$Result:ident,
+
+) => {
+        pub struct $Name {
+            file: ::std::fs::File,
+        }
+
+        impl $Name {
+            const ERASE_VALUE: u8 = $erase_value;
+            /// The file must be opened for reading and writing.
+            pub fn new(file: ::std::fs::File) -> Self {
+                $Name { file }
+            }
+
+            pub fn into_inner(self) -> ::std::fs::File {
+                self.file
+            }
+
+            fn seek(&self, offset: usize) -> $Result<()> {
+                use ::std::io::Seek;
+                (&self.file)
+                    .seek(::std::io::SeekFrom::Start(offset as u64))
+                    .map(drop)
+                    .map_err(|_| $crate::io::Error::Io)
+            }
+        }
+
+        impl $StorageTrait for $Name {
+            const READ_SIZE: usize = $read_size;
+            const WRITE_SIZE: usize = $write_size;
+            type CACHE_SIZE = $cache_size;
+            const BLOCK_SIZE: usize = $block_size;
+            const BLOCK_COUNT: usize = $block_count;
+            type LOOKAHEADWORDS_SIZE = $lookaheadwords_size;
+
+            fn read(&self, offset: usize, buf: &mut [u8]) -> $Result<usize> {
+                use ::std::io::Read;
+                self.seek(offset)?;
+                // past the end of a short image, read as erased
+                let mut read = 0;
+                while read < buf.len() {
+                    match (&self.file).read(&mut buf[read..]) {
+                        Ok(0) => break,
+                        Ok(n) => read += n,
+                        Err(_) => return Err($crate::io::Error::Io),
+                    }
+                }
+                for byte in buf[read..].iter_mut() {
+                    *byte = Self::ERASE_VALUE;
+                }
+                Ok(buf.len())
+            }
+
+            fn write(&mut self, offset: usize, data: &[u8]) -> $Result<usize> {
+                use ::std::io::Write;
+                self.seek(offset)?;
+                (&self.file).write_all(data).map_err(|_| $crate::io::Error::Io)?;
+                Ok(data.len())
+            }
+
+            fn erase(&mut self, offset: usize, len: usize) -> $Result<usize> {
+                use ::std::io::Write;
+                self.seek(offset)?;
+                let erased = [Self::ERASE_VALUE; $block_size];
+                for _ in 0..len / $block_size {
+                    (&self.file).write_all(&erased).map_err(|_| $crate::io::Error::Io)?;
+                }
+                Ok(len)
+            }
+        }
+    };
+}
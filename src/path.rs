@@ -1,6 +1,8 @@
 //! Paths
 
-use core::{convert::TryFrom, fmt, marker::PhantomData, ops, ptr, slice, str};
+use core::{ascii, convert::TryFrom, fmt::{self, Write as _}, ops, ptr, slice, str};
+#[cfg(feature = "serde")]
+use core::marker::PhantomData;
 
 use cstr_core::CStr;
 use cty::{c_char, size_t};
@@ -65,6 +67,11 @@ impl Path {
         self.inner.as_ptr()
     }
 
+    /// The bytes of the path, without the trailing nul.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        self.inner.to_bytes()
+    }
+
     /// Creates an owned `PathBuf` with `path` adjoined to `self`.
     pub fn join(&self, path: &Path) -> PathBuf {
         let mut p = PathBuf::from(self);
@@ -72,6 +79,93 @@ impl Path {
         p
     }
 
+    /// Whether the path starts with `/`.
+    pub fn is_absolute(&self) -> bool {
+        self.inner.to_bytes().first() == Some(&b'/')
+    }
+
+    /// The rest of the path after `base`, if `base` is a prefix made of whole components.
+    ///
+    /// Separators between `base` and the rest are dropped, so `/a/b/c` stripped of `/a`
+    /// is `b/c`, while `/ab` does not start with `/a`.
+    pub fn strip_prefix(&self, base: &Path) -> Option<&Path> {
+        let bytes = self.inner.to_bytes_with_nul();
+        let base = base.as_ref().as_bytes();
+        let base = base.strip_suffix(b"/").unwrap_or(base);
+        let mut rest = bytes.strip_prefix(base)?;
+        match rest[0] {
+            b'/' => {
+                while rest[0] == b'/' {
+                    rest = &rest[1..];
+                }
+            }
+            b'\0' => {}
+            _ if base.is_empty() => {}
+            _ => return None,
+        }
+        // NOTE(unsafe) a suffix of a path, including its trailing nul
+        Some(unsafe { Path::from_bytes_with_nul_unchecked(rest) })
+    }
+
+    /// The path with repeated and trailing separators removed.
+    ///
+    /// littlefs has no notion of the current or parent directory, so paths containing
+    /// `.` or `..` components are rejected with `DotComponent`.
+    pub fn normalize(&self) -> Result<PathBuf> {
+        let bytes = self.as_ref().as_bytes();
+        let mut normal = [0u8; consts::PATH_MAX];
+        let mut len = 0;
+        if self.is_absolute() {
+            normal[0] = b'/';
+            len = 1;
+        }
+        for component in bytes.split(|byte| *byte == b'/').filter(|c| !c.is_empty()) {
+            if component == b"." || component == b".." {
+                return Err(Error::DotComponent);
+            }
+            if len > 0 && normal[len - 1] != b'/' {
+                normal[len] = b'/';
+                len += 1;
+            }
+            normal[len..][..component.len()].copy_from_slice(component);
+            len += component.len();
+        }
+        PathBuf::try_from_bytes(&normal[..len])
+    }
+
+    /// Whether the whole path matches the glob `pattern`.
+    ///
+    /// `*` matches any number of bytes, including `/`, and `?` matches exactly one byte.
+    /// All other bytes match themselves; there are no character classes or escapes.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let (pattern, name) = (pattern.as_bytes(), self.inner.to_bytes());
+        let (mut p, mut n) = (0, 0);
+        // position after the last `*`, and where in the name it started matching
+        let mut star = None;
+        while n < name.len() {
+            match pattern.get(p) {
+                Some(b'*') => {
+                    p += 1;
+                    star = Some((p, n));
+                }
+                Some(&byte) if byte == b'?' || byte == name[n] => {
+                    p += 1;
+                    n += 1;
+                }
+                // let the last `*` take one more byte
+                _ => match star {
+                    Some((after_star, start)) => {
+                        p = after_star;
+                        n = start + 1;
+                        star = Some((after_star, n));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|&byte| byte == b'*')
+    }
+
     pub fn exists<S: crate::driver::Storage>(&self, fs: &crate::fs::Filesystem<S>) -> bool {
         fs.metadata(self).is_ok()
     }
@@ -105,16 +199,35 @@ impl AsRef<str> for Path {
     }
 }
 
+/// Quotes, backslashes and anything but printable ASCII are escaped, e.g. `p"/caf\xe9\0"`.
 impl fmt::Debug for Path {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // helpful for debugging wither the trailing nul is indeed a trailing nul.
-        write!(f, "p{:?}", self.as_str_ref_with_trailing_nul())
+        f.write_str("p\"")?;
+        for &byte in self.inner.to_bytes() {
+            for escaped in ascii::escape_default(byte) {
+                f.write_char(escaped as char)?;
+            }
+        }
+        f.write_str("\\0\"")
     }
 }
 
+/// Printable ASCII is written as is, other bytes are escaped like `\xe9`.
+///
+/// Names read from the storage are not checked to be ASCII, this never fails on them.
 impl fmt::Display for Path {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(self.as_ref())
+        for &byte in self.inner.to_bytes() {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                f.write_char(byte as char)?;
+            } else {
+                for escaped in ascii::escape_default(byte) {
+                    f.write_char(escaped as char)?;
+                }
+            }
+        }
+        Ok(())
     }
 }
 
@@ -132,6 +245,34 @@ impl PartialEq<str> for Path {
     }
 }
 
+impl PartialEq for Path {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.to_bytes() == other.inner.to_bytes()
+    }
+}
+
+impl Eq for Path {}
+
+/// Plain byte-lexicographic order, not component-wise: `"/a-b"` sorts before `"/a/b"`,
+/// since `'-'` is less than `'/'`.
+impl PartialOrd for Path {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Path {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.inner.to_bytes().cmp(other.inner.to_bytes())
+    }
+}
+
+impl core::hash::Hash for Path {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.inner.to_bytes().hash(state)
+    }
+}
+
 // without this you need to slice byte string literals (`b"foo\0"[..].try_into()`)
 macro_rules! array_impls {
     ($($N:expr),+) => {
@@ -165,14 +306,20 @@ array_impls!(
     28, 29, 30, 31, 32
 );
 
-/// An owned, mutable path
+/// An owned, mutable path with room for `N - 1` bytes
+///
+/// `N` is at most `consts::PATH_MAX_PLUS_ONE`, smaller buffers save RAM where paths are
+/// known to be short. Converting between capacities is fallible, see `try_from_path`.
 #[derive(Clone)]
-pub struct PathBuf {
-    buf: [c_char; consts::PATH_MAX_PLUS_ONE],
+pub struct PathBufN<const N: usize> {
+    buf: [c_char; N],
     // NOTE `len` DOES include the final null byte
     len: usize,
 }
 
+/// An owned, mutable path of up to `consts::PATH_MAX` bytes
+pub type PathBuf = PathBufN<{ consts::PATH_MAX_PLUS_ONE }>;
+
 /// # Safety
 /// `s` must point to valid memory; `s` will be treated as a null terminated string
 unsafe fn strlen(mut s: *const c_char) -> size_t {
@@ -184,19 +331,52 @@ unsafe fn strlen(mut s: *const c_char) -> size_t {
     n
 }
 
-impl PathBuf {
+impl<const N: usize> PathBufN<N> {
+    /// The maximum length of the path, without the trailing nul.
+    pub const CAPACITY: usize = N - 1;
+
     pub fn new() -> Self {
-        Self { buf: [0; consts::PATH_MAX_PLUS_ONE], len: 1 }
+        assert!(N > 0 && N <= consts::PATH_MAX_PLUS_ONE);
+        Self { buf: [0; N], len: 1 }
     }
 
     pub fn clear(&mut self) {
-        self.buf = [0; consts::PATH_MAX_PLUS_ONE];
+        self.buf = [0; N];
         self.len = 1;
     }
 
-    pub(crate) unsafe fn from_buffer(buf: [c_char; consts::PATH_MAX_PLUS_ONE]) -> Self {
-        let len = strlen(buf.as_ptr()) + 1 /* null byte */;
-        PathBuf { buf, len }
+    /// Creates a path from a byte string, with or without trailing nul.
+    ///
+    /// Fails with `NotCStr` if there are embedded nuls, `NotAscii` for non-ASCII bytes,
+    /// and `TooLarge` if there are more than `CAPACITY` bytes.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self> {
+        // NB: This needs to set the final NUL byte, unless it already has one
+        let bytes = match bytes.split_last() {
+            Some((b'\0', rest)) => rest,
+            _ => bytes,
+        };
+        if bytes.contains(&b'\0') {
+            return Err(Error::NotCStr);
+        }
+        if bytes.len() > Self::CAPACITY || bytes.len() > consts::PATH_MAX {
+            return Err(Error::TooLarge);
+        }
+        if !bytes.is_ascii() {
+            return Err(Error::NotAscii);
+        }
+
+        let mut buf = [0; N];
+        let len = bytes.len();
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), buf.as_mut_ptr().cast(), len) }
+        Ok(Self {
+            buf,
+            len: len + 1,
+        })
+    }
+
+    /// Copies `path`, failing with `TooLarge` if it does not fit.
+    pub fn try_from_path(path: &Path) -> Result<Self> {
+        Self::try_from_bytes(path.as_ref().as_bytes())
     }
 
     /// Extends `self` with `path`
@@ -237,7 +417,7 @@ impl PathBuf {
                 } else {
                     0
                 }
-                <= consts::PATH_MAX_PLUS_ONE
+                <= N
         );
 
         let len = self.len;
@@ -255,13 +435,21 @@ impl PathBuf {
     }
 }
 
-impl From<&Path> for PathBuf {
+impl PathBuf {
+    pub(crate) unsafe fn from_buffer(buf: [c_char; consts::PATH_MAX_PLUS_ONE]) -> Self {
+        let len = strlen(buf.as_ptr()) + 1 /* null byte */;
+        PathBuf { buf, len }
+    }
+}
+
+impl<const N: usize> From<&Path> for PathBufN<N> {
+    /// PANICS: when `path` does not fit, use `PathBufN::try_from_path` to check.
     fn from(path: &Path) -> Self {
         let bytes = path.as_ref().as_bytes();
 
-        let mut buf = [0; consts::PATH_MAX_PLUS_ONE];
+        let mut buf = [0; N];
         let len = bytes.len();
-        assert!(len <= consts::PATH_MAX);
+        assert!(len < N);
         unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), buf.as_mut_ptr().cast(), len + 1) }
         Self {
             buf,
@@ -270,40 +458,26 @@ impl From<&Path> for PathBuf {
     }
 }
 
-impl From<&[u8]> for PathBuf {
+impl<const N: usize> From<&[u8]> for PathBufN<N> {
     /// Accepts byte string, with or without trailing nul.
     ///
-    /// PANICS: when there are embedded nuls
+    /// PANICS: when there are embedded nuls, non-ASCII bytes, or more than
+    /// `CAPACITY` bytes; use `PathBufN::try_from_bytes` for untrusted input.
     fn from(bytes: &[u8]) -> Self {
-        // NB: This needs to set the final NUL byte, unless it already has one
-        // It also checks that there are no inner NUL bytes
-        let bytes = if !bytes.is_empty() && bytes[bytes.len() - 1] == b'\0' {
-            &bytes[..bytes.len() - 1]
-        } else {
-            bytes
-        };
-        let has_no_embedded_nul = bytes.iter().find(|&&byte| byte == b'\0').is_none();
-        assert!(has_no_embedded_nul);
-
-        let mut buf = [0; consts::PATH_MAX_PLUS_ONE];
-        let len = bytes.len();
-        assert!(len <= consts::PATH_MAX);
-        assert!(bytes.is_ascii());
-        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), buf.as_mut_ptr().cast(), len) }
-        Self {
-            buf,
-            len: len + 1,
+        match Self::try_from_bytes(bytes) {
+            Ok(path) => path,
+            Err(error) => panic!("invalid path: {:?}", error),
         }
     }
 }
 
-impl From<&str> for PathBuf {
+impl<const N: usize> From<&str> for PathBufN<N> {
     fn from(s: &str) -> Self {
-        PathBuf::from(s.as_bytes())
+        Self::from(s.as_bytes())
     }
 }
 
-impl ops::Deref for PathBuf {
+impl<const N: usize> ops::Deref for PathBufN<N> {
     type Target = Path;
 
     fn deref(&self) -> &Path {
@@ -317,59 +491,88 @@ impl ops::Deref for PathBuf {
 }
 
 
-impl serde::Serialize for PathBuf {
+/// Serialized as a string; paths are ASCII, hence valid UTF-8.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Path {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        // names read from the storage are not checked to be UTF-8
+        let path = str::from_utf8(self.inner.to_bytes()).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(path)
+    }
+}
+
+/// Serialized as a string, like `Path`.
+#[cfg(feature = "serde")]
+impl<const N: usize> serde::Serialize for PathBufN<N> {
     fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        serializer.serialize_bytes(self.as_ref().as_bytes())
+        <Path as serde::Serialize>::serialize(self, serializer)
     }
 }
 
-impl<'de> serde::Deserialize<'de> for PathBuf
+/// Deserialized from a string, or from bytes as written by earlier versions.
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for PathBufN<N>
 {
     fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        struct ValueVisitor<'de>(PhantomData<&'de ()>);
+        struct ValueVisitor<'de, const N: usize>(PhantomData<&'de ()>);
 
-        impl<'de> serde::de::Visitor<'de> for ValueVisitor<'de>
+        impl<'de, const N: usize> serde::de::Visitor<'de> for ValueVisitor<'de, N>
         {
-            type Value = PathBuf;
+            type Value = PathBufN<N>;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
                 formatter.write_str("a path buffer")
             }
 
+            fn visit_str<E>(self, v: &str) -> core::result::Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                match PathBufN::try_from_bytes(v.as_bytes()) {
+                    Ok(path) => Ok(path),
+                    Err(Error::TooLarge) => Err(E::invalid_length(v.len(), &self)),
+                    Err(_) => Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
+                }
+            }
+
             fn visit_bytes<E>(self, v: &[u8]) -> core::result::Result<Self::Value, E>
             where
                 E: serde::de::Error,
             {
-                if v.len() > consts::PATH_MAX {
-                    return Err(E::invalid_length(v.len(), &self));
+                match PathBufN::try_from_bytes(v) {
+                    Ok(path) => Ok(path),
+                    Err(Error::TooLarge) => Err(E::invalid_length(v.len(), &self)),
+                    Err(_) => Err(E::invalid_value(serde::de::Unexpected::Bytes(v), &self)),
                 }
-                Ok(PathBuf::from(v))
             }
         }
 
-        deserializer.deserialize_bytes(ValueVisitor(PhantomData))
+        deserializer.deserialize_str(ValueVisitor(PhantomData))
     }
 }
 
-impl fmt::Debug for PathBuf {
+impl<const N: usize> fmt::Debug for PathBufN<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         <Path as fmt::Debug>::fmt(self, f)
     }
 }
 
-impl fmt::Display for PathBuf {
+impl<const N: usize> fmt::Display for PathBufN<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         <Path as fmt::Display>::fmt(self, f)
     }
 }
 
-impl core::cmp::PartialEq for PathBuf {
+impl<const N: usize> core::cmp::PartialEq for PathBufN<N> {
     fn eq(&self, other: &Self) -> bool {
         // from cstr_core
         self.as_ref() == other.as_ref()
@@ -382,24 +585,29 @@ impl core::cmp::PartialEq for PathBuf {
     }
 }
 
-impl core::cmp::Eq for PathBuf {}
+impl<const N: usize> core::cmp::Eq for PathBufN<N> {}
 
-// use core::cmp::Ordering;
+/// Byte-lexicographic, like `Path`.
+impl<const N: usize> PartialOrd for PathBufN<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-// impl Ord for PathBuf {
-//     fn cmp(&self, other: &Self) -> Ordering {
-//         self.len.cmp(&other.len)
-//     }
-// }
+impl<const N: usize> Ord for PathBufN<N> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        <Path as Ord>::cmp(self, other)
+    }
+}
 
-// impl PartialOrd for PathBuf {
-//     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-//         Some(self.cmp(other))
-//     }
-// }
+impl<const N: usize> core::hash::Hash for PathBufN<N> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        <Path as core::hash::Hash>::hash(self, state)
+    }
+}
 
 /// Errors that arise from converting byte buffers into paths
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Error {
     /// Byte buffer contains non-ASCII characters
     NotAscii,
@@ -407,6 +615,8 @@ pub enum Error {
     NotCStr,
     /// Byte buffer is too long (longer than `consts::PATH_MAX_PLUS_ONE`)
     TooLarge,
+    /// Path contains a `.` or `..` component
+    DotComponent,
 }
 
 /// Result type that has its Error variant set to `path::Error`
@@ -414,7 +624,8 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 #[cfg(test)]
 mod tests {
-    use super::{Path, PathBuf};
+    use super::{Error, Path, PathBuf, PathBufN};
+    use crate::consts;
 
     #[test]
     fn join() {
@@ -453,4 +664,132 @@ mod tests {
     fn trailing_nuls() {
         assert_eq!(PathBuf::from("abc"), PathBuf::from("abc\0"));
     }
+
+    #[test]
+    fn strip_prefix() {
+        let path = Path::from_bytes_with_nul(b"/a/b/c\0").unwrap();
+        assert!(path.is_absolute());
+        let a = Path::from_bytes_with_nul(b"/a\0").unwrap();
+        let rest = path.strip_prefix(a).unwrap();
+        assert_eq!(rest, "b/c");
+        assert!(!rest.is_absolute());
+
+        let a_slash = Path::from_bytes_with_nul(b"/a/\0").unwrap();
+        assert_eq!(path.strip_prefix(a_slash).unwrap(), "b/c");
+        let root = Path::from_bytes_with_nul(b"/\0").unwrap();
+        assert_eq!(path.strip_prefix(root).unwrap(), "a/b/c");
+        assert_eq!(path.strip_prefix(path).unwrap(), "");
+
+        let other = Path::from_bytes_with_nul(b"/b\0").unwrap();
+        assert!(path.strip_prefix(other).is_none());
+        let partial = Path::from_bytes_with_nul(b"/a/b/cd\0").unwrap();
+        assert!(partial.strip_prefix(path).is_none());
+        assert!(a.strip_prefix(path).is_none());
+    }
+
+    #[test]
+    fn normalize() {
+        let normal = |bytes: &[u8]| Path::from_bytes_with_nul(bytes).unwrap().normalize();
+        assert_eq!(normal(b"//a///b/\0").unwrap(), PathBuf::from("/a/b"));
+        assert_eq!(normal(b"/a/b\0").unwrap(), PathBuf::from("/a/b"));
+        assert_eq!(normal(b"a//b\0").unwrap(), PathBuf::from("a/b"));
+        assert_eq!(normal(b"///\0").unwrap(), PathBuf::from("/"));
+        assert_eq!(normal(b"\0").unwrap(), PathBuf::from(""));
+        assert_eq!(normal(b"/a/.hidden\0").unwrap(), PathBuf::from("/a/.hidden"));
+
+        assert_eq!(normal(b"/a/./b\0").unwrap_err(), Error::DotComponent);
+        assert_eq!(normal(b"/a/..\0").unwrap_err(), Error::DotComponent);
+        assert_eq!(normal(b".\0").unwrap_err(), Error::DotComponent);
+    }
+
+    #[test]
+    fn matches() {
+        fn path(bytes: &[u8]) -> &Path {
+            Path::from_bytes_with_nul(bytes).unwrap()
+        }
+        assert!(path(b"abc\0").matches("a?c"));
+        assert!(!path(b"ac\0").matches("a?c"));
+        assert!(!path(b"abbc\0").matches("a?c"));
+
+        assert!(path(b"rk.key\0").matches("*.key"));
+        assert!(path(b".key\0").matches("*.key"));
+        assert!(path(b"a.key.key\0").matches("*.key"));
+        assert!(!path(b"rk.key.bak\0").matches("*.key"));
+        assert!(path(b"rk.key.bak\0").matches("*.key*"));
+        assert!(path(b"abcbd\0").matches("a*b?"));
+        assert!(!path(b"abc\0").matches("a*b?d"));
+
+        assert!(path(b"\0").matches(""));
+        assert!(path(b"\0").matches("**"));
+        assert!(!path(b"a\0").matches(""));
+        assert!(!path(b"\0").matches("?"));
+    }
+
+    #[test]
+    fn capacity() {
+        type ShortPathBuf = PathBufN<17>;
+        assert_eq!(ShortPathBuf::CAPACITY, 16);
+        assert_eq!(PathBuf::CAPACITY, consts::PATH_MAX);
+
+        let short = ShortPathBuf::try_from_bytes(b"/fido/rk").unwrap();
+        assert_eq!(&*short, "/fido/rk");
+        assert!(ShortPathBuf::try_from_bytes(b"/fido/rk/0123456789a").is_err());
+        assert_eq!(
+            ShortPathBuf::try_from_bytes(b"/fido/rk/0123456789a").unwrap_err(),
+            Error::TooLarge,
+        );
+        assert!(ShortPathBuf::try_from_bytes(&[b'a'; 16]).is_ok());
+
+        let long = PathBuf::from("/fido/rk/0123456789a");
+        assert_eq!(ShortPathBuf::try_from_path(&long).unwrap_err(), Error::TooLarge);
+        let widened = PathBuf::try_from_path(&short).unwrap();
+        assert_eq!(&*widened, "/fido/rk");
+        assert_eq!(&*short.join(Path::from_bytes_with_nul(b"x\0").unwrap()), "/fido/rk/x");
+    }
+
+    #[test]
+    fn try_from_bytes() {
+        assert_eq!(PathBuf::try_from_bytes(b"/a/b").unwrap(), PathBuf::from("/a/b"));
+        assert_eq!(PathBuf::try_from_bytes(b"/a/b\0").unwrap(), PathBuf::from("/a/b"));
+        assert_eq!(PathBuf::try_from_bytes(b"").unwrap(), PathBuf::new());
+
+        assert_eq!(PathBuf::try_from_bytes(b"/a\0b").unwrap_err(), Error::NotCStr);
+        assert_eq!(PathBuf::try_from_bytes(b"/a\0\0").unwrap_err(), Error::NotCStr);
+        assert_eq!(PathBuf::try_from_bytes("/\u{e4}".as_bytes()).unwrap_err(), Error::NotAscii);
+
+        let longest = [b'a'; consts::PATH_MAX];
+        assert!(PathBuf::try_from_bytes(&longest).is_ok());
+        let too_long = [b'a'; consts::PATH_MAX + 1];
+        assert_eq!(PathBuf::try_from_bytes(&too_long).unwrap_err(), Error::TooLarge);
+    }
+
+    #[test]
+    fn format_non_ascii() {
+        // names read from the storage are not checked
+        let mut buf = [0; consts::PATH_MAX_PLUS_ONE];
+        for (to, from) in buf.iter_mut().zip(b"caf\xe9 \"x\"\t") {
+            *to = *from as _;
+        }
+        let name = unsafe { PathBuf::from_buffer(buf) };
+        assert_eq!(std::format!("{}", name), "caf\\xe9 \"x\"\\t");
+        assert_eq!(std::format!("{:?}", name), "p\"caf\\xe9 \\\"x\\\"\\t\\0\"");
+
+        let plain = PathBuf::from("/a b/c");
+        assert_eq!(std::format!("{}", plain), "/a b/c");
+        assert_eq!(std::format!("{:?}", plain), "p\"/a b/c\\0\"");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_non_utf8() {
+        let mut buf = [0; consts::PATH_MAX_PLUS_ONE];
+        for (to, from) in buf.iter_mut().zip(b"caf\xe9") {
+            *to = *from as _;
+        }
+        let name = unsafe { PathBuf::from_buffer(buf) };
+        assert!(serde_json::to_string(&name).is_err());
+
+        let plain = PathBuf::from("/a b/c");
+        assert_eq!(serde_json::to_string(&plain).unwrap(), r#""/a b/c""#);
+    }
 }
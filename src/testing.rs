@@ -0,0 +1,192 @@
+//! Conformance checks for `driver::Storage` implementations.
+//!
+//! Driver authors can call [`run_storage_conformance`](fn.run_storage_conformance.html)
+//! from a test (or on target) to check their implementation behaves the way littlefs expects.
+//! Enabled via the `testing` feature.
+
+use generic_array::typenum::marker_traits::Unsigned;
+
+use crate::{
+    driver::Storage,
+    fs::{Bytes, Filesystem},
+    io,
+    path::Path,
+};
+
+/// The invariant a storage implementation violated.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Failure {
+    /// `READ_SIZE` or `WRITE_SIZE` is zero.
+    ZeroIoSize,
+    /// `CACHE_SIZE` is not a multiple of `READ_SIZE`.
+    CacheNotMultipleOfRead,
+    /// `CACHE_SIZE` is not a multiple of `WRITE_SIZE`.
+    CacheNotMultipleOfWrite,
+    /// `BLOCK_SIZE` is not a multiple of `CACHE_SIZE`.
+    BlockNotMultipleOfCache,
+    /// `BLOCK_SIZE` is less than 128 bytes.
+    BlockTooSmall,
+    /// `BLOCK_COUNT` is less than 2, leaving no room for the superblock pair.
+    TooFewBlocks,
+    /// A driver method reported fewer bytes than it was asked to handle.
+    ShortTransfer,
+    /// After `erase`, the block did not read back as one repeated erase value.
+    EraseNotUniform,
+    /// Data read back differs from what was written.
+    ReadBackMismatch,
+    /// A filesystem operation failed.
+    Filesystem(io::Error),
+    /// A file did not have the contents written to it.
+    FileMismatch,
+    /// A removed file could still be found.
+    FileNotRemoved,
+}
+
+impl From<io::Error> for Failure {
+    fn from(error: io::Error) -> Self {
+        Failure::Filesystem(error)
+    }
+}
+
+/// Run a series of checks against storages produced by `make`.
+///
+/// Each phase starts from a freshly made storage:
+/// - the geometry constants are checked against the requirements of littlefs
+/// - the raw `erase`, `write` and `read` methods are exercised on the first block
+/// - a filesystem is formatted and mounted, and files are created, read back,
+///   remounted and removed
+///
+/// The first violated invariant is returned.
+pub fn run_storage_conformance<S: Storage>(make: impl Fn() -> S) -> Result<(), Failure> {
+    check_geometry::<S>()?;
+    check_raw(&mut make())?;
+    check_filesystem(&mut make())
+}
+
+fn check_geometry<S: Storage>() -> Result<(), Failure> {
+    let cache_size = S::CACHE_SIZE::USIZE;
+    if S::READ_SIZE == 0 || S::WRITE_SIZE == 0 {
+        return Err(Failure::ZeroIoSize);
+    }
+    if cache_size % S::READ_SIZE != 0 {
+        return Err(Failure::CacheNotMultipleOfRead);
+    }
+    if cache_size % S::WRITE_SIZE != 0 {
+        return Err(Failure::CacheNotMultipleOfWrite);
+    }
+    if cache_size == 0 || S::BLOCK_SIZE % cache_size != 0 {
+        return Err(Failure::BlockNotMultipleOfCache);
+    }
+    if S::BLOCK_SIZE < 128 {
+        return Err(Failure::BlockTooSmall);
+    }
+    if S::BLOCK_COUNT < 2 {
+        return Err(Failure::TooFewBlocks);
+    }
+    Ok(())
+}
+
+fn check_raw<S: Storage>(storage: &mut S) -> Result<(), Failure> {
+    let mut buf: Bytes<S::CACHE_SIZE> = Default::default();
+    let chunks = S::BLOCK_SIZE / buf.len();
+
+    if storage.erase(0, S::BLOCK_SIZE)? != S::BLOCK_SIZE {
+        return Err(Failure::ShortTransfer);
+    }
+    let erase_value = read_chunk(storage, 0, &mut buf)?[0];
+    for i in 0..chunks {
+        let chunk = read_chunk(storage, i * buf.len(), &mut buf)?;
+        if chunk.iter().any(|byte| *byte != erase_value) {
+            return Err(Failure::EraseNotUniform);
+        }
+    }
+
+    for i in 0..chunks {
+        fill_pattern(&mut buf, i);
+        if storage.write(i * buf.len(), &buf)? != buf.len() {
+            return Err(Failure::ShortTransfer);
+        }
+    }
+    let mut expected: Bytes<S::CACHE_SIZE> = Default::default();
+    for i in 0..chunks {
+        fill_pattern(&mut expected, i);
+        if read_chunk(storage, i * buf.len(), &mut buf)? != &expected[..] {
+            return Err(Failure::ReadBackMismatch);
+        }
+    }
+
+    storage.erase(0, S::BLOCK_SIZE)?;
+    for i in 0..chunks {
+        let chunk = read_chunk(storage, i * buf.len(), &mut buf)?;
+        if chunk.iter().any(|byte| *byte != erase_value) {
+            return Err(Failure::EraseNotUniform);
+        }
+    }
+    Ok(())
+}
+
+fn read_chunk<'b, S: Storage>(storage: &S, off: usize, buf: &'b mut [u8]) -> Result<&'b [u8], Failure> {
+    if storage.read(off, buf)? != buf.len() {
+        return Err(Failure::ShortTransfer);
+    }
+    Ok(buf)
+}
+
+fn fill_pattern(buf: &mut [u8], seed: usize) {
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = (i + 7 * seed) as u8 ^ 0x5a;
+    }
+}
+
+fn check_filesystem<S: Storage>(storage: &mut S) -> Result<(), Failure> {
+    let dir = Path::from_bytes_with_nul(b"/conformance\0").unwrap();
+    let small = Path::from_bytes_with_nul(b"/conformance/small\0").unwrap();
+    let large = Path::from_bytes_with_nul(b"/conformance/large\0").unwrap();
+
+    // files larger than the cache are never inlined
+    let mut contents: Bytes<S::CACHE_SIZE> = Default::default();
+    fill_pattern(&mut contents, 1);
+    let large_len = contents.len() + 1;
+
+    Filesystem::format(storage)?;
+    Filesystem::mount_and_then(storage, |fs| {
+        fs.create_dir(dir)?;
+        fs.write(small, b"conformance")?;
+        fs.create_file_and_then(large, |file| {
+            use io::Write;
+            file.write_all(&contents)?;
+            file.write_all(&contents[..1])
+        })
+    })?;
+
+    // fresh allocation, so nothing is served from littlefs' caches
+    Filesystem::mount_and_then(storage, |fs| {
+        let read: heapless::Vec<u8, 16> = fs.read(small)?;
+        if read != b"conformance" {
+            return Ok(Err(Failure::FileMismatch));
+        }
+
+        let mut buf: Bytes<S::CACHE_SIZE> = Default::default();
+        let matches = fs.open_file_and_then(large, |file| {
+            if file.len()? != large_len || file.read(&mut buf)? != buf.len() || buf != contents {
+                return Ok(false);
+            }
+            Ok(file.read(&mut buf)? == 1 && buf[0] == contents[0])
+        })?;
+        if !matches {
+            return Ok(Err(Failure::FileMismatch));
+        }
+
+        fs.remove(small)?;
+        fs.remove(large)?;
+        fs.remove(dir)?;
+        Ok(Ok(()))
+    })??;
+
+    Filesystem::mount_and_then(storage, |fs| {
+        if fs.metadata(dir) != Err(io::Error::NoSuchEntry) {
+            return Ok(Err(Failure::FileNotRemoved));
+        }
+        Ok(Ok(()))
+    })?
+}
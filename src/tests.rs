@@ -13,6 +13,7 @@ use crate::{
         Read,
         SeekFrom,
     },
+    path::{Path, PathBuf},
     driver,
 };
 
@@ -450,3 +451,2466 @@ fn test_iter_dirs() {
 //     t.compile_fail("tests/ui/*-fail.rs");
 //     t.pass("tests/ui/*-pass.rs");
 // }
+
+#[test]
+fn content_hash() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"hashed.bin\0".try_into().unwrap();
+        let mut contents = [0u8; 1000];
+        for (i, byte) in contents.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        fs.write(path, &contents)?;
+
+        let mut buf = [0u8; 1024];
+        assert_eq!(fs.read_verified_by_attr(path, &mut buf).unwrap_err(), Error::NoAttribute);
+
+        fs.set_content_hash(path)?;
+        assert_eq!(fs.read_verified_by_attr(path, &mut buf)?, 1000);
+        assert_eq!(&buf[..1000], &contents[..]);
+
+        // too small a buffer can't be verified
+        assert_eq!(fs.read_verified_by_attr(path, &mut buf[..999]).unwrap_err(), Error::FileTooBig);
+
+        // corrupt one byte, leaving the stored hash alone
+        fs.open_file_with_options_and_then(
+            |options| options.write(true),
+            path,
+            |file| {
+                file.seek(SeekFrom::Start(500))?;
+                file.write(b"\x00")
+            },
+        )?;
+        assert_eq!(fs.read_verified_by_attr(path, &mut buf).unwrap_err(), Error::Corruption);
+
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn unmount_and_remount() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+
+    let mut alloc = Filesystem::allocate();
+    let fs = Filesystem::mount(&mut alloc, &mut storage).unwrap();
+    fs.write(b"persistent.txt\0".try_into().unwrap(), b"still here").unwrap();
+    fs.unmount().unwrap();
+
+    // fresh allocation, same storage
+    let mut alloc = Filesystem::allocate();
+    let fs = Filesystem::mount(&mut alloc, &mut storage).unwrap();
+    let contents: heapless::Vec<_, 32> = fs.read(b"persistent.txt\0".try_into().unwrap()).unwrap();
+    assert_eq!(contents, b"still here");
+    fs.unmount().unwrap();
+}
+
+#[test]
+fn mount_and_then_storage_full() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+
+    let data = [0x33; 1400];
+    let mut written = 0u8;
+    let result = Filesystem::mount_and_then(&mut storage, |fs| {
+        loop {
+            fs.write(&PathBuf::from(&[b'/', b'a' + written][..]), &data)?;
+            written += 1;
+        }
+    });
+    assert_eq!(result, Err::<(), _>(Error::NoSpace));
+    assert!(written > 0);
+
+    // unmounted despite the error, the files written before are all there
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        for i in 0..written {
+            let contents: heapless::Vec<_, 1400> = fs.read(&PathBuf::from(&[b'/', b'a' + i][..]))?;
+            assert_eq!(contents, &data[..]);
+        }
+        assert!(!fs.exists(&PathBuf::from(&[b'/', b'a' + written][..])));
+        fs.remove(&PathBuf::from(&b"/a"[..]))?;
+        fs.write(&PathBuf::from(&b"/a"[..]), &data)
+    }).unwrap();
+}
+
+#[test]
+fn file_closed_after_closure_error() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/partial\0".try_into().unwrap();
+        let result = fs.create_file_and_then(path, |file| {
+            file.write(b"written before the error")?;
+            Err::<(), _>(Error::Invalid)
+        });
+        assert_eq!(result, Err(Error::Invalid));
+        assert_eq!(fs.open_file_count(), 0);
+
+        // closing synced the data, and nothing holds the file open for writing
+        let result = fs.open_file_and_then(path, |file| {
+            let mut buf = [0u8; 32];
+            let len = file.read(&mut buf)?;
+            assert_eq!(&buf[..len], b"written before the error");
+            Err::<(), _>(Error::Io)
+        });
+        assert_eq!(result, Err(Error::Io));
+        assert_eq!(fs.open_file_count(), 0);
+        fs.write(path, b"overwritten")
+    }).unwrap();
+}
+
+#[test]
+fn file_closed_after_closure_panic() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/locked\0".try_into().unwrap();
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            fs.create_file_and_then(path, |file| -> Result<()> {
+                file.write(b"abandoned")?;
+                panic!("closure gave up");
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(fs.open_file_count(), 0);
+
+        // no handle lingers, so the file can be opened for writing again
+        fs.create_file_and_then(path, |file| file.write(b"taken over"))?;
+        let contents: heapless::Vec<_, 16> = fs.read(path)?;
+        assert_eq!(contents, b"taken over");
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn max_open_files() {
+    use driver::Storage;
+
+    struct TwoFiles<'s, S: Storage>(&'s mut S);
+
+    impl<S: Storage> Storage for TwoFiles<'_, S> {
+        const READ_SIZE: usize = S::READ_SIZE;
+        const WRITE_SIZE: usize = S::WRITE_SIZE;
+        const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+        const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+        const MAX_OPEN_FILES: usize = 2;
+        type CACHE_SIZE = S::CACHE_SIZE;
+        type LOOKAHEADWORDS_SIZE = S::LOOKAHEADWORDS_SIZE;
+
+        fn read(&self, off: usize, buf: &mut [u8]) -> Result<usize> {
+            self.0.read(off, buf)
+        }
+        fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+            self.0.write(off, data)
+        }
+        fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+            self.0.erase(off, len)
+        }
+    }
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    let mut storage = TwoFiles(&mut storage);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let a: &Path = b"/a\0".try_into().unwrap();
+        let b: &Path = b"/b\0".try_into().unwrap();
+        let c: &Path = b"/c\0".try_into().unwrap();
+        for path in [a, b, c].iter() {
+            fs.write(path, b"x")?;
+        }
+
+        fs.open_file_and_then(a, |_| {
+            fs.open_file_and_then(b, |_| {
+                assert_eq!(
+                    fs.open_file_and_then(c, |_| Ok(())),
+                    Err(Error::TooManyOpenFiles),
+                );
+                // the refused handle is not counted
+                assert_eq!(fs.open_file_count(), 2);
+                Ok(())
+            })?;
+            // one handle is free again, open directories do not take one
+            fs.read_dir_and_then(b"/\0".try_into().unwrap(), |_| {
+                fs.open_file_and_then(c, |_| Ok(()))
+            })
+        })
+    }).unwrap();
+}
+
+#[test]
+fn find() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/logs\0".try_into().unwrap())?;
+        fs.write(b"/logs/first.log\0".try_into().unwrap(), b"first")?;
+        for i in 0..20u8 {
+            let name = [b'/', b'z', b'0' + i / 10, b'0' + i % 10];
+            fs.write(&PathBuf::from(&name[..]), &[i])?;
+        }
+
+        let mut visited = 0;
+        let found = fs.find(b"/\0".try_into().unwrap(), |path, metadata| {
+            visited += 1;
+            metadata.is_file() && path.as_ref().ends_with(".log")
+        })?;
+        assert_eq!(found.unwrap(), PathBuf::from("/logs/first.log"));
+        // "/logs" and its file, at most a few others
+        assert!(visited < 20);
+
+        assert!(fs.find(b"/\0".try_into().unwrap(), |_, metadata| metadata.len() > 100)?.is_none());
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn traverse() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/tmp\0".try_into().unwrap())?;
+        fs.write(b"/tmp/large.bin\0".try_into().unwrap(), &[0x2a; 2000])?;
+
+        let mut blocks = std::collections::BTreeSet::new();
+        fs.traverse(|block| {
+            blocks.insert(block);
+            Ok(())
+        })?;
+        // superblock pair
+        assert!(blocks.contains(&0) && blocks.contains(&1));
+        assert!(blocks.iter().all(|&block| (block as usize) < fs.total_blocks()));
+        assert!(blocks.len() >= 2 + 2 + 2000 / 256);
+
+        // errors abort the traversal and are passed on
+        let mut visited = 0;
+        let result = fs.traverse(|_| {
+            visited += 1;
+            Err(Error::Corruption)
+        });
+        assert_eq!(result, Err(Error::Corruption));
+        assert_eq!(visited, 1);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn used_block_bitmap() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/tmp\0".try_into().unwrap())?;
+        fs.write(b"/tmp/large.bin\0".try_into().unwrap(), &[0x2a; 2000])?;
+
+        let mut bitmap = [0xff; 512 / 8];
+        let used = fs.used_block_bitmap(&mut bitmap)?;
+        let set_bits: usize = bitmap.iter().map(|byte| byte.count_ones() as usize).sum();
+        assert_eq!(used, set_bits);
+        // superblock pair
+        assert_eq!(bitmap[0] & 0b11, 0b11);
+        assert!(used >= 2 + 2 + 2000 / 256);
+        assert!(used <= fs.total_blocks() - fs.available_blocks()?);
+
+        let mut blocks = std::collections::BTreeSet::new();
+        fs.traverse(|block| {
+            blocks.insert(block);
+            Ok(())
+        })?;
+        assert_eq!(used, blocks.len());
+
+        let mut too_small = [0; 512 / 8 - 1];
+        assert_eq!(fs.used_block_bitmap(&mut too_small), Err(Error::Invalid));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn format_one_partition() {
+    use driver::PartitionStorage;
+    type First<'p, 's> = PartitionStorage<'p, OtherRamStorage<'s>, 0, 256>;
+    type Second<'p, 's> = PartitionStorage<'p, OtherRamStorage<'s>, 256, 256>;
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    Filesystem::format(&mut First::new(&mut storage)).unwrap();
+    Filesystem::format(&mut Second::new(&mut storage)).unwrap();
+    Filesystem::mount_and_then(&mut Second::new(&mut storage), |fs| {
+        fs.write(b"data.bin\0".try_into().unwrap(), b"second partition")
+    }).unwrap();
+
+    let second_half = storage.backend.buf[256 * 256..].to_vec();
+    Filesystem::mount_and_then(&mut First::new(&mut storage), |fs| {
+        fs.write(b"config.bin\0".try_into().unwrap(), b"first partition")
+    }).unwrap();
+    // reformatting the first partition...
+    Filesystem::format(&mut First::new(&mut storage)).unwrap();
+    // ...leaves the second one alone
+    assert_eq!(&storage.backend.buf[256 * 256..], &second_half[..]);
+
+    Filesystem::mount_and_then(&mut First::new(&mut storage), |fs| {
+        assert!(!PathBuf::from("config.bin").exists(fs));
+        Ok(())
+    }).unwrap();
+    Filesystem::mount_and_then(&mut Second::new(&mut storage), |fs| {
+        let contents: heapless::Vec<_, 32> = fs.read(b"data.bin\0".try_into().unwrap())?;
+        assert_eq!(contents, b"second partition");
+        Ok(())
+    }).unwrap();
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn read_dir_vec() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/tmp\0".try_into().unwrap())?;
+        for name in &["/tmp/a", "/tmp/b", "/tmp/c"] {
+            fs.write(&PathBuf::from(*name), b"")?;
+        }
+        let entries = fs.read_dir_vec(b"/tmp\0".try_into().unwrap())?;
+        // ".", ".." and three files
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries.iter().filter(|entry| entry.file_type().is_file()).count(), 3);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn recover_atomic() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let cfg = b"/cfg\0".try_into().unwrap();
+        let a = b"/cfg/a\0".try_into().unwrap();
+        let b = b"/cfg/b\0".try_into().unwrap();
+        let c = b"/cfg/c\0".try_into().unwrap();
+        let d = b"/cfg/d\0".try_into().unwrap();
+        let e = b"/cfg/e\0".try_into().unwrap();
+        fs.create_dir(cfg)?;
+
+        // swap of a and b, interrupted after the first rename
+        fs.write(a, b"A")?;
+        fs.write(b, b"B")?;
+        let mut partner = Attribute::new(crate::consts::SWAP_PARTNER_ATTRIBUTE_ID);
+        fs.set_attribute(a, partner.set_data(b"b"))?;
+        fs.rename(a, b"/cfg/a.tmp~\0".try_into().unwrap())?;
+
+        // swap of c and d, interrupted after the second rename
+        fs.write(c, b"C")?;
+        fs.write(d, b"D")?;
+        fs.set_attribute(c, partner.set_data(b"d"))?;
+        fs.rename(c, b"/cfg/c.tmp~\0".try_into().unwrap())?;
+        fs.rename(d, c)?;
+
+        // not left by a swap
+        let unrelated = b"/cfg/e.tmp~\0".try_into().unwrap();
+        fs.write(e, b"E")?;
+        fs.write(unrelated, b"unrelated")?;
+
+        // partners that are not file names in `cfg`
+        let nested = b"/cfg/f.tmp~\0".try_into().unwrap();
+        fs.write(nested, b"nested")?;
+        fs.set_attribute(nested, partner.set_data(b"e/f"))?;
+        let garbled = b"/cfg/g.tmp~\0".try_into().unwrap();
+        fs.write(garbled, b"garbled")?;
+        fs.set_attribute(garbled, partner.set_data(b"\xff\0g"))?;
+
+        assert_eq!(fs.recover_atomic(cfg)?, 2);
+
+        let contents: heapless::Vec<_, 8> = fs.read(a)?;
+        assert_eq!(contents, b"A");
+        let contents: heapless::Vec<_, 8> = fs.read(b)?;
+        assert_eq!(contents, b"B");
+        let contents: heapless::Vec<_, 8> = fs.read(c)?;
+        assert_eq!(contents, b"D");
+        let contents: heapless::Vec<_, 8> = fs.read(d)?;
+        assert_eq!(contents, b"C");
+        let contents: heapless::Vec<_, 16> = fs.read(unrelated)?;
+        assert_eq!(contents, b"unrelated");
+        let contents: heapless::Vec<_, 16> = fs.read(nested)?;
+        assert_eq!(contents, b"nested");
+        let contents: heapless::Vec<_, 16> = fs.read(garbled)?;
+        assert_eq!(contents, b"garbled");
+        assert_eq!(fs.attribute(a, crate::consts::SWAP_PARTNER_ATTRIBUTE_ID)?, None);
+        assert_eq!(fs.attribute(d, crate::consts::SWAP_PARTNER_ATTRIBUTE_ID)?, None);
+
+        assert_eq!(fs.recover_atomic(cfg)?, 0);
+
+        // both names in place: reported, nothing is deleted
+        let temp = b"/cfg/a.tmp~\0".try_into().unwrap();
+        fs.write(temp, b"X")?;
+        fs.set_attribute(temp, partner.set_data(b"b"))?;
+        assert_eq!(fs.recover_atomic(cfg), Err(Error::EntryAlreadyExisted));
+        let contents: heapless::Vec<_, 8> = fs.read(temp)?;
+        assert_eq!(contents, b"X");
+        let contents: heapless::Vec<_, 8> = fs.read(a)?;
+        assert_eq!(contents, b"A");
+        let contents: heapless::Vec<_, 8> = fs.read(b)?;
+        assert_eq!(contents, b"B");
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn rename_swap() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let cfg = b"/cfg\0".try_into().unwrap();
+        let live = b"/cfg/live\0".try_into().unwrap();
+        let next = b"/cfg/next\0".try_into().unwrap();
+        fs.create_dir(cfg)?;
+        fs.write(live, b"old config")?;
+        fs.write(next, b"new config, somewhat longer")?;
+
+        fs.rename_swap(live, next)?;
+        let contents: heapless::Vec<_, 32> = fs.read(live)?;
+        assert_eq!(contents, b"new config, somewhat longer");
+        let contents: heapless::Vec<_, 32> = fs.read(next)?;
+        assert_eq!(contents, b"old config");
+        assert_eq!(fs.recover_atomic(cfg)?, 0);
+        assert_eq!(fs.attribute(next, crate::consts::SWAP_PARTNER_ATTRIBUTE_ID)?, None);
+
+        assert_eq!(fs.rename_swap(live, b"/other\0".try_into().unwrap()), Err(Error::Invalid));
+        assert_eq!(fs.rename_swap(live, b"/cfg/missing\0".try_into().unwrap()), Err(Error::NoSuchEntry));
+        assert_eq!(fs.rename_swap(live, live), Err(Error::Invalid));
+        let dir = b"/cfg/dir\0".try_into().unwrap();
+        fs.create_dir(dir)?;
+        assert_eq!(fs.rename_swap(live, dir), Err(Error::Invalid));
+        assert_eq!(fs.rename_swap(dir, live), Err(Error::Invalid));
+        assert_eq!(fs.attribute(live, crate::consts::SWAP_PARTNER_ATTRIBUTE_ID)?, None);
+        let contents: heapless::Vec<_, 32> = fs.read(live)?;
+        assert_eq!(contents, b"new config, somewhat longer");
+        Ok(())
+    }).unwrap();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn slow_storage() {
+    use core::time::Duration;
+    use driver::{SlowStorage, Storage};
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    let (read, write, erase) = (Duration::from_micros(5), Duration::from_micros(20), Duration::from_micros(300));
+    let mut slow = SlowStorage::new(&mut storage, read, write, erase);
+
+    slow.erase(0, 256).unwrap();
+    slow.write(0, &[0x42; 64]).unwrap();
+    slow.write(64, &[0x43; 32]).unwrap();
+    let mut buf = [0; 96];
+    for _ in 0..3 {
+        assert_eq!(slow.read(0, &mut buf).unwrap(), 96);
+    }
+    assert_eq!(&buf[..64], &[0x42; 64][..]);
+    assert_eq!(&buf[64..], &[0x43; 32][..]);
+    assert_eq!(slow.elapsed(), erase + write * 2 + read * 3);
+
+    slow.reset_elapsed();
+    Filesystem::format(&mut slow).unwrap();
+    assert!(slow.elapsed() >= erase);
+}
+
+#[test]
+fn slice_storage() {
+    use driver::SliceStorage;
+    type Storage<'a> = SliceStorage<'a, 8192, 512, consts::U32, consts::U1>;
+
+    let mut buf = [0u8; 8192];
+    let mut storage = Storage::new(&mut buf);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(fs.total_blocks(), 16);
+        fs.write(b"/data\0".try_into().unwrap(), &[0x5a; 1000])
+    }).unwrap();
+
+    // the contents stay in the buffer
+    let buf = storage.into_inner();
+    let mut storage = Storage::new(buf);
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let contents: heapless::Vec<_, 1000> = fs.read(b"/data\0".try_into().unwrap())?;
+        assert_eq!(contents, &[0x5a; 1000][..]);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn remap_bad_blocks() {
+    use driver::{RemapStorage, Storage};
+
+    // fails to erase or write some blocks, like worn out flash
+    struct BadBlocks<'s, S: Storage> {
+        storage: &'s mut S,
+        bad: [usize; 4],
+    }
+
+    impl<S: Storage> BadBlocks<'_, S> {
+        fn check(&self, off: usize) -> Result<()> {
+            if self.bad.contains(&(off / S::BLOCK_SIZE)) {
+                return Err(Error::Io);
+            }
+            Ok(())
+        }
+    }
+
+    impl<S: Storage> Storage for BadBlocks<'_, S> {
+        const READ_SIZE: usize = S::READ_SIZE;
+        const WRITE_SIZE: usize = S::WRITE_SIZE;
+        const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+        const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+        type CACHE_SIZE = S::CACHE_SIZE;
+        type LOOKAHEADWORDS_SIZE = S::LOOKAHEADWORDS_SIZE;
+
+        fn read(&self, off: usize, buf: &mut [u8]) -> Result<usize> {
+            self.storage.read(off, buf)
+        }
+        fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+            self.check(off)?;
+            self.storage.write(off, data)
+        }
+        fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+            self.check(off)?;
+            self.storage.erase(off, len)
+        }
+    }
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    // a file over half of the device covers at least two of them, wherever it starts
+    let mut bad_blocks = BadBlocks { storage: &mut storage, bad: [50, 180, 310, 440] };
+    let path = b"/large\0".try_into().unwrap();
+    let data: std::vec::Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let mut remap = RemapStorage::<_, 5>::new(&mut bad_blocks).unwrap();
+    assert_eq!(remap.remapped_blocks(), 0);
+    Filesystem::format(&mut remap).unwrap();
+    Filesystem::mount_and_then(&mut remap, |fs| fs.write(path, &data)).unwrap();
+    let remapped = remap.remapped_blocks();
+    assert!(remapped >= 2);
+
+    // the table survives, and the data is found in the spares
+    let mut remap = RemapStorage::<_, 5>::new(&mut bad_blocks).unwrap();
+    assert_eq!(remap.remapped_blocks(), remapped);
+    Filesystem::mount_and_then(&mut remap, |fs| {
+        let mut contents = std::vec![0; data.len() + 1];
+        assert_eq!(fs.read_into(path, &mut contents)?, data.len());
+        assert!(contents[..data.len()] == data[..]);
+        Ok(())
+    }).unwrap();
+
+    // each change of the table overwrites the older of the two copies at the end,
+    // if the newest one is lost, e.g. to power loss while writing it, the other one is used
+    let newest = if remapped % 2 == 1 { 510 } else { 511 };
+    bad_blocks.storage.erase(newest * 256, 256).unwrap();
+    let remap = RemapStorage::<_, 5>::new(&mut bad_blocks).unwrap();
+    assert_eq!(remap.remapped_blocks(), remapped - 1);
+
+    // a spare is only taken if the table recording it is stored
+    bad_blocks.bad = [20, 20, 510, 511];
+    let mut remap = RemapStorage::<_, 5>::new(&mut bad_blocks).unwrap();
+    assert_eq!(remap.write(20 * 256, &[0x5a; 256]), Err(Error::Io));
+    assert_eq!(remap.remapped_blocks(), remapped - 1);
+}
+
+#[test]
+fn power_loss_during_write() {
+    use driver::FaultyStorage;
+    let path = b"/file\0".try_into().unwrap();
+    let old = [b'o'; 600];
+    let new = [b'n'; 600];
+
+    // cut the power at every write of the update in turn, until one goes through
+    let mut completed = false;
+    for n in 1..200 {
+        let mut backend = OtherRam::default();
+        let mut storage = OtherRamStorage::new(&mut backend);
+        Filesystem::format(&mut storage).unwrap();
+        Filesystem::mount_and_then(&mut storage, |fs| fs.write(path, &old)).unwrap();
+
+        let mut faulty = FaultyStorage::new(&mut storage);
+        faulty.fail_nth_write(n);
+        let result = Filesystem::mount_and_then(&mut faulty, |fs| fs.write(path, &new));
+        if !faulty.is_tripped() {
+            result.unwrap();
+            completed = true;
+        } else {
+            assert!(result.is_err());
+        }
+
+        Filesystem::mount_and_then(faulty.into_inner(), |fs| {
+            let contents: heapless::Vec<_, 1024> = fs.read(path)?;
+            assert!(contents == old || contents == new);
+            if completed {
+                assert_eq!(contents, new);
+            }
+            Ok(())
+        }).unwrap();
+
+        if completed {
+            break;
+        }
+    }
+    assert!(completed);
+}
+
+#[test]
+fn checksummed_storage() {
+    use driver::{ChecksummedStorage, Storage};
+    type Checksummed<'s, 'b> = ChecksummedStorage<'s, OtherRamStorage<'b>, consts::U56>;
+    assert_eq!(Checksummed::READ_SIZE, 28);
+    assert_eq!(Checksummed::BLOCK_SIZE, 8 * 28);
+
+    let path = b"/file\0".try_into().unwrap();
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    let mut checksummed = Checksummed::new(&mut storage);
+    Filesystem::format(&mut checksummed).unwrap();
+    Filesystem::mount_and_then(&mut checksummed, |fs| {
+        fs.write(path, &[0x5a; 600])?;
+        let contents: heapless::Vec<_, 600> = fs.read(path)?;
+        assert_eq!(contents, &[0x5a; 600][..]);
+        Ok(())
+    }).unwrap();
+
+    // flip a bit in the file's contents
+    let position = backend.buf.windows(28).position(|unit| unit == [0x5a; 28]).unwrap();
+    backend.buf[position + 10] ^= 0x01;
+
+    let mut storage = OtherRamStorage::new(&mut backend);
+    let mut checksummed = Checksummed::new(&mut storage);
+    Filesystem::mount_and_then(&mut checksummed, |fs| {
+        let contents: Result<heapless::Vec<_, 600>> = fs.read(path);
+        assert_eq!(contents, Err(Error::Io));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn check_integrity() {
+    use driver::ChecksummedStorage;
+    type Checksummed<'s, 'b> = ChecksummedStorage<'s, OtherRamStorage<'b>, consts::U56>;
+
+    let path = b"/file\0".try_into().unwrap();
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    let mut checksummed = Checksummed::new(&mut storage);
+    Filesystem::format(&mut checksummed).unwrap();
+    Filesystem::mount_and_then(&mut checksummed, |fs| {
+        fs.write(path, &[0x5a; 600])?;
+        let report = fs.check_integrity()?;
+        assert!(report.is_clean());
+        assert_eq!(report.first_bad_block, None);
+        assert!(report.blocks_visited >= 2 + 600 / (8 * 28));
+        Ok(())
+    }).unwrap();
+
+    // flip a bit in the file's contents
+    let position = backend.buf.windows(28).position(|unit| unit == [0x5a; 28]).unwrap();
+    backend.buf[position + 10] ^= 0x01;
+    let damaged = position / 256;
+
+    let mut storage = OtherRamStorage::new(&mut backend);
+    let mut checksummed = Checksummed::new(&mut storage);
+    Filesystem::mount_and_then(&mut checksummed, |fs| {
+        let report = fs.check_integrity()?;
+        assert!(!report.is_clean());
+        assert_eq!(report.out_of_range, 0);
+        assert_eq!(report.unreadable, 1);
+        assert_eq!(report.first_bad_block, Some(damaged));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn walk_dir() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/opcard\0".try_into().unwrap())?;
+        fs.create_dir(b"/opcard/keys\0".try_into().unwrap())?;
+        fs.create_dir(b"/fido\0".try_into().unwrap())?;
+        fs.create_dir(b"/fido/rk\0".try_into().unwrap())?;
+        fs.create_dir(b"/fido/rk/site\0".try_into().unwrap())?;
+        fs.write(b"/opcard/state\0".try_into().unwrap(), b"state")?;
+        fs.write(b"/opcard/keys/sign\0".try_into().unwrap(), b"sign")?;
+        fs.write(b"/opcard/keys/auth\0".try_into().unwrap(), b"auth")?;
+        fs.write(b"/fido/counter\0".try_into().unwrap(), b"1")?;
+        fs.write(b"/fido/rk/site/cred\0".try_into().unwrap(), b"credential")?;
+
+        let mut files = 0;
+        let mut dirs = 0;
+        let mut bytes = 0;
+        fs.walk_dir(b"/\0".try_into().unwrap(), |path, metadata| {
+            if metadata.is_file() {
+                assert!(fs.metadata(path)?.is_file());
+                files += 1;
+                bytes += metadata.len();
+            } else {
+                dirs += 1;
+            }
+            Ok(())
+        })?;
+        assert_eq!(files, 5);
+        assert_eq!(dirs, 5);
+        assert_eq!(bytes, 5 + 4 + 4 + 1 + 10);
+
+        let mut under_fido = 0;
+        fs.walk_dir(b"/fido\0".try_into().unwrap(), |path, _| {
+            assert!(path.as_ref().starts_with("/fido/"));
+            under_fido += 1;
+            Ok(())
+        })?;
+        assert_eq!(under_fido, 4);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn walk_large_dir() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        // enough entries to split the directory over several metadata pairs
+        fs.create_dir(b"/rk\0".try_into().unwrap())?;
+        for i in 0..64 {
+            let path = PathBuf::from(format!("/rk/credential-{:02}", i).as_str());
+            fs.write(&path, &[i as u8; 16])?;
+        }
+
+        let mut visited = 0;
+        fs.walk_dir(b"/\0".try_into().unwrap(), |path, metadata| {
+            if metadata.is_file() {
+                assert_eq!(path, format!("/rk/credential-{:02}", visited - 1).as_str());
+            }
+            visited += 1;
+            Ok(())
+        })?;
+        assert_eq!(visited, 65);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn entries() {
+    use crate::fs::FileType;
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/opcard\0".try_into().unwrap())?;
+        fs.create_dir(b"/opcard/keys\0".try_into().unwrap())?;
+        fs.create_dir(b"/fido\0".try_into().unwrap())?;
+        fs.write(b"/opcard/state\0".try_into().unwrap(), b"state")?;
+        fs.write(b"/opcard/keys/sign\0".try_into().unwrap(), b"sign")?;
+        fs.write(b"/fido/counter\0".try_into().unwrap(), b"1")?;
+
+        let mut entries: heapless::Vec<(PathBuf, FileType), 8> = heapless::Vec::new();
+        for entry in fs.entries(b"/\0".try_into().unwrap()) {
+            let (path, metadata) = entry?;
+            // directories come before their contents
+            if let Some(parent) = path.parent().filter(|parent| &**parent != "") {
+                assert!(entries.iter().any(|(path, _)| *path == parent));
+            }
+            entries.push((path, metadata.file_type())).unwrap();
+        }
+
+        // depth first, and by name within each directory
+        let expected = [
+            ("/fido", FileType::Dir),
+            ("/fido/counter", FileType::File),
+            ("/opcard", FileType::Dir),
+            ("/opcard/keys", FileType::Dir),
+            ("/opcard/keys/sign", FileType::File),
+            ("/opcard/state", FileType::File),
+        ];
+        assert_eq!(entries.len(), expected.len());
+        for ((path, file_type), (expected_path, expected_type)) in entries.iter().zip(expected.iter()) {
+            assert_eq!(&**path, *expected_path);
+            assert_eq!(file_type, expected_type);
+        }
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn export_tar() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/fido\0".try_into().unwrap())?;
+        fs.create_dir(b"/fido/rk\0".try_into().unwrap())?;
+        fs.write(b"/fido/counter\0".try_into().unwrap(), b"1")?;
+        fs.write(b"/other\0".try_into().unwrap(), b"not exported")?;
+        let mut attribute = Attribute::new(7);
+        attribute.set_data(b"seven");
+        fs.set_attribute(b"/fido/counter\0".try_into().unwrap(), &attribute)?;
+
+        let mut archive: heapless::Vec<u8, 64> = heapless::Vec::new();
+        fs.export_tar(b"/fido\0".try_into().unwrap(), &mut archive)?;
+        let expected: &[u8] = &[
+            b'L', b'F', b'A', b'1',
+            b'f', 7, b'c', b'o', b'u', b'n', b't', b'e', b'r', 1, 0, 0, 0, b'1',
+            b'a', 7, 5, 0, b's', b'e', b'v', b'e', b'n',
+            b'd', 2, b'r', b'k',
+            b'e',
+        ];
+        assert_eq!(&archive[..], expected);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn import_tar() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    let root: &Path = b"/\0".try_into().unwrap();
+    let counter: &Path = b"/fido/counter\0".try_into().unwrap();
+    let cred: &Path = b"/fido/rk/cred\0".try_into().unwrap();
+    let state: &Path = b"/state\0".try_into().unwrap();
+
+    let mut archive: heapless::Vec<u8, 1024> = heapless::Vec::new();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/fido\0".try_into().unwrap())?;
+        fs.create_dir(b"/fido/rk\0".try_into().unwrap())?;
+        fs.write(counter, b"1")?;
+        fs.write(cred, &[0x5a; 300])?;
+        fs.write(state, b"state")?;
+        let mut attribute = Attribute::new(7);
+        attribute.set_data(b"seven");
+        fs.set_attribute(counter, &attribute)?;
+        fs.export_tar(root, &mut archive)
+    }).unwrap();
+
+    let check = |fs: &Filesystem<'_, OtherRamStorage<'_>>| -> Result<()> {
+        let contents: heapless::Vec<_, 300> = fs.read(counter)?;
+        assert_eq!(contents, b"1");
+        assert_eq!(fs.attribute(counter, 7)?.unwrap().data(), b"seven");
+        assert!(fs.is_dir(b"/fido/rk\0".try_into().unwrap()));
+        Ok(())
+    };
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.import_tar(root, &mut archive.iter().copied())?;
+        check(fs)?;
+        let contents: heapless::Vec<_, 300> = fs.read(cred)?;
+        assert_eq!(contents, &[0x5a; 300][..]);
+        let contents: heapless::Vec<_, 300> = fs.read(state)?;
+        assert_eq!(contents, b"state");
+        Ok(())
+    }).unwrap();
+
+    // cut off in the contents of `cred`, which is followed by `state` and the end marker
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let truncated = &archive[..archive.len() - 20];
+        assert_eq!(fs.import_tar(root, &mut truncated.iter().copied()), Err(Error::Corruption));
+        check(fs)?;
+        assert!(!fs.exists(cred));
+        assert!(!fs.exists(state));
+
+        // a file that was there before is not removed
+        fs.write(cred, b"old")?;
+        assert_eq!(fs.import_tar(root, &mut truncated.iter().copied()), Err(Error::Corruption));
+        assert!(fs.exists(cred));
+
+        let mut wrong_magic = archive.clone();
+        wrong_magic[3] = b'2';
+        assert_eq!(fs.import_tar(root, &mut wrong_magic.iter().copied()), Err(Error::Corruption));
+        let escaping = b"LFA1d\x02..e";
+        assert_eq!(fs.import_tar(root, &mut escaping.iter().copied()), Err(Error::Corruption));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn append() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/log\0".try_into().unwrap();
+        fs.append(path, b"first,")?;
+        fs.append(path, b"second,")?;
+        fs.append(path, b"third")?;
+        let contents: heapless::Vec<_, 32> = fs.read(path)?;
+        assert_eq!(contents, b"first,second,third");
+
+        // in append mode, writes go to the end even after seeking
+        fs.open_file_with_options_and_then(
+            |options| options.read(true).write(true).append(true),
+            path,
+            |file| {
+                file.seek(SeekFrom::Start(0))?;
+                file.write(b"!")?;
+                Ok(())
+            },
+        )?;
+        let contents: heapless::Vec<_, 32> = fs.read(path)?;
+        assert_eq!(contents, b"first,second,third!");
+        Ok(())
+    }).unwrap();
+}
+
+#[cfg(feature = "dir-entry-path")]
+#[test]
+fn open_file_at() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/fido\0".try_into().unwrap())?;
+        fs.write(b"/fido/counter\0".try_into().unwrap(), b"42")?;
+
+        fs.read_dir_and_then(b"/fido\0".try_into().unwrap(), |dir| {
+            dir.create_file_at(b"key\0".try_into().unwrap(), |file| file.write(b"secret"))?;
+            let mut buf = [0u8; 8];
+            let read = dir.open_file_at(b"counter\0".try_into().unwrap(), |file| file.read(&mut buf))?;
+            assert_eq!(&buf[..read], b"42");
+            let read = dir.open_file_at(b"key\0".try_into().unwrap(), |file| file.read(&mut buf))?;
+            assert_eq!(&buf[..read], b"secret");
+            Ok(())
+        })?;
+
+        let key: heapless::Vec<_, 8> = fs.read(b"/fido/key\0".try_into().unwrap())?;
+        assert_eq!(key, b"secret");
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn open_file_count() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let a = b"/a\0".try_into().unwrap();
+        let b = b"/b\0".try_into().unwrap();
+        fs.write(a, b"a")?;
+        fs.write(b, b"b")?;
+        assert_eq!(fs.open_file_count(), 0);
+
+        fs.open_file_and_then(a, |_| {
+            assert_eq!(fs.open_file_count(), 1);
+            fs.open_file_and_then(b, |_| {
+                assert_eq!(fs.open_file_count(), 2);
+                Ok(())
+            })?;
+            // open directories do not count
+            fs.read_dir_and_then(b"/\0".try_into().unwrap(), |_| {
+                assert_eq!(fs.open_file_count(), 1);
+                Ok(())
+            })
+        })?;
+        assert_eq!(fs.open_file_count(), 0);
+
+        fs.open_file_and_then(b"/missing\0".try_into().unwrap(), |_| Ok(())).ok();
+        assert_eq!(fs.open_file_count(), 0);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn write_read_only() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/file\0".try_into().unwrap();
+        fs.write(path, b"original")?;
+        fs.open_file_and_then(path, |file| {
+            assert_eq!(file.write(b"changed"), Err(Error::BadFileDescriptor));
+            Ok(())
+        })?;
+        let contents: heapless::Vec<_, 16> = fs.read(path)?;
+        assert_eq!(contents, b"original");
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn read_header() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/image.png\0".try_into().unwrap();
+        fs.write(path, b"\x89PNG\r\n\x1a\n and the rest")?;
+        let magic = fs.read_header::<consts::U4>(path)?;
+        assert_eq!(magic.as_slice(), b"\x89PNG");
+
+        let short = b"/short\0".try_into().unwrap();
+        fs.write(short, b"ab")?;
+        let header = fs.read_header::<consts::U4>(short)?;
+        assert_eq!(header.as_slice(), b"ab\0\0");
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn open_with_attributes() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path: &Path = b"/signed\0".try_into().unwrap();
+        let mut attributes = [Attribute::new(1), Attribute::new(2)];
+        attributes[0].set_data(b"version 2");
+        attributes[1].set_data(&[0x5a; 64]);
+        crate::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open_with_attributes_and_then(fs, path, &mut attributes, |file| {
+                file.write(b"contents")
+            })?;
+
+        let contents: heapless::Vec<_, 16> = fs.read(path)?;
+        assert_eq!(contents, b"contents");
+        assert_eq!(fs.attribute(path, 1)?.unwrap().data(), b"version 2");
+        assert_eq!(fs.attribute(path, 2)?.unwrap().data(), &[0x5a; 64][..]);
+
+        // opened for reading, the stored values are read into the attributes
+        let mut attributes = [Attribute::new(1)];
+        attributes[0].set_data(&[0; 9]);
+        crate::fs::OpenOptions::new()
+            .read(true)
+            .open_with_attributes_and_then(fs, path, &mut attributes, |_| Ok(()))?;
+        assert_eq!(attributes[0].data(), b"version 2");
+
+        // another open writer makes opening check for a writer first,
+        // which must not read the stored values into the new ones
+        fs.create_file_and_then(b"/other\0".try_into().unwrap(), |_| {
+            let mut attributes = [Attribute::new(1)];
+            attributes[0].set_data(b"version 3");
+            crate::fs::OpenOptions::new()
+                .write(true)
+                .open_with_attributes_and_then(fs, path, &mut attributes, |_| Ok(()))
+        })?;
+        assert_eq!(fs.attribute(path, 1)?.unwrap().data(), b"version 3");
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn reuse_file_allocation() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let files: [(&Path, &[u8]); 3] = [
+            (b"/a\0".try_into().unwrap(), b"first"),
+            (b"/b\0".try_into().unwrap(), b"second file"),
+            (b"/c\0".try_into().unwrap(), b"third"),
+        ];
+        for (path, contents) in files.iter() {
+            fs.write(path, contents)?;
+        }
+
+        let mut alloc = File::allocate();
+        for (path, contents) in files.iter() {
+            let mut buf = [0u8; 16];
+            let len = File::open_with_allocation_and_then(fs, &mut alloc, path, |file| {
+                file.read(&mut buf)
+            })?;
+            assert_eq!(&buf[..len], *contents);
+        }
+        assert_eq!(fs.open_file_count(), 0);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn write_with_allocation() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/handle\0".try_into().unwrap();
+        let mut alloc = File::allocate();
+        let mut options = crate::fs::OpenOptions::new();
+        options.write(true).create(true);
+        options.open_with_allocation_and_then(fs, &mut alloc, path, |file| {
+            file.write(b"through the opened handle")
+        })?;
+
+        let mut buf = [0u8; 32];
+        let len = File::open_with_allocation_and_then(fs, &mut alloc, path, |file| file.read(&mut buf))?;
+        assert_eq!(&buf[..len], b"through the opened handle");
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn with_raw() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path: &Path = b"/raw\0".try_into().unwrap();
+        fs.write(path, b"accessed directly")?;
+
+        let mut info: crate::ll::lfs_info = unsafe { core::mem::MaybeUninit::zeroed().assume_init() };
+        unsafe { fs.with_raw(|lfs, _| crate::ll::lfs_stat(lfs, path.as_ptr(), &mut info))? };
+        assert_eq!(crate::fs::Metadata::from(info), fs.metadata(path)?);
+
+        let missing: &Path = b"/missing\0".try_into().unwrap();
+        let result = unsafe { fs.with_raw(|lfs, _| crate::ll::lfs_stat(lfs, missing.as_ptr(), &mut info)) };
+        assert_eq!(result, Err(Error::NoSuchEntry));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn write_fails_cleanly() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/blob\0".try_into().unwrap();
+        let data = [0x5a; 1000];
+        fs.write(path, &data)?;
+        let contents: heapless::Vec<_, 1000> = fs.read(path)?;
+        assert_eq!(contents, &data[..]);
+
+        let too_large = [0xa5; 32 * 700];
+        assert_eq!(fs.write(path, &too_large), Err(Error::NoSpace));
+        // the failed write is not committed
+        let contents: heapless::Vec<_, 1000> = fs.read(path)?;
+        assert_eq!(contents, &data[..]);
+
+        // a file created by the failed write is removed
+        let new = b"/new\0".try_into().unwrap();
+        assert_eq!(fs.write(new, &too_large), Err(Error::NoSpace));
+        assert!(!fs.exists(new));
+        // the space is reclaimed
+        fs.write(new, &data)?;
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn sync_open_files() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    let path: &Path = b"/pending\0".try_into().unwrap();
+    let data = [0x5a; 1000];
+    {
+        let mut alloc = Filesystem::allocate();
+        let fs = Filesystem::mount(&mut alloc, &mut storage).unwrap();
+        let mut file_alloc = crate::fs::FileAllocation::new();
+        let file = unsafe {
+            crate::fs::OpenOptions::new().write(true).create(true).open(&fs, &mut file_alloc, path)
+        }.unwrap();
+        crate::io::Write::write_all(&file, &data).unwrap();
+        fs.sync().unwrap();
+        // neither closed nor unmounted, as if power was lost
+    }
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let contents: heapless::Vec<_, 1000> = fs.read(path)?;
+        assert_eq!(contents, &data[..]);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn file_name_too_long() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let mut name = [b'a'; 301];
+        name[0] = b'/';
+        // rejected when building the path, littlefs is never asked
+        let create = || -> Result<()> { fs.create_dir(&PathBuf::try_from_bytes(&name)?) };
+        assert_eq!(create(), Err(Error::FilenameTooLong));
+
+        name[300] = 0;
+        assert_eq!(Path::from_bytes_with_nul(&name).map_err(Error::from).err(), Some(Error::FilenameTooLong));
+        assert_eq!(fs.dir_entry_count(b"/\0".try_into().unwrap())?, 0);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn touch() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let fresh: &Path = b"/fresh\0".try_into().unwrap();
+        fs.touch(fresh)?;
+        assert!(fs.is_file(fresh));
+        assert_eq!(fs.metadata(fresh)?.len(), 0);
+
+        let lock: &Path = b"/lock\0".try_into().unwrap();
+        fs.write(lock, b"pid 42")?;
+        fs.touch(lock)?;
+        let contents: heapless::Vec<_, 16> = fs.read(lock)?;
+        assert_eq!(contents, b"pid 42");
+
+        assert_eq!(fs.touch(b"/missing/lock\0".try_into().unwrap()), Err(Error::NoSuchEntry));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn remount() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    let mut alloc = Filesystem::allocate();
+    let fs = Filesystem::mount(&mut alloc, &mut storage).unwrap();
+    let path: &Path = b"/kept\0".try_into().unwrap();
+    fs.write(path, b"across remounts").unwrap();
+
+    let fs = fs.remount().unwrap();
+    let contents: heapless::Vec<_, 32> = fs.read(path).unwrap();
+    assert_eq!(contents, b"across remounts");
+    fs.unmount().unwrap();
+}
+
+#[test]
+fn no_space_code() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let too_large = [0xa5; 32 * 700];
+        let error = fs.write(b"/blob\0".try_into().unwrap(), &too_large).unwrap_err();
+        // the littlefs code survives the mapping
+        assert_eq!(error.code(), crate::ll::lfs_error_LFS_ERR_NOSPC);
+        assert_eq!(Error::from(error.code()), error);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn read_to_string() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let config: &Path = b"/config\0".try_into().unwrap();
+        fs.write(config, "name = \"gr\u{fc}n\"\n".as_bytes())?;
+        File::open_and_then(fs, config, |file| {
+            let mut contents: heapless::String<32> = heapless::String::new();
+            assert_eq!(file.read_to_string(&mut contents)?, 15);
+            assert_eq!(contents, "name = \"gr\u{fc}n\"\n");
+            Ok(())
+        })?;
+
+        let binary: &Path = b"/binary\0".try_into().unwrap();
+        fs.write(binary, &[b'o', b'k', 0xff, 0xfe])?;
+        File::open_and_then(fs, binary, |file| {
+            let mut contents: heapless::String<32> = heapless::String::from("kept");
+            assert_eq!(file.read_to_string(&mut contents), Err(Error::Invalid));
+            assert_eq!(contents, "kept");
+            Ok(())
+        })
+    }).unwrap();
+}
+
+#[test]
+fn read_into() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/config\0".try_into().unwrap();
+        fs.write(path, b"12345678")?;
+
+        let mut larger = [0u8; 16];
+        assert_eq!(fs.read_into(path, &mut larger)?, 8);
+        assert_eq!(&larger[..8], b"12345678");
+
+        let mut exact = [0u8; 8];
+        assert_eq!(fs.read_into(path, &mut exact)?, 8);
+        assert_eq!(&exact, b"12345678");
+
+        let mut smaller = [0u8; 5];
+        assert_eq!(fs.read_into(path, &mut smaller)?, 5);
+        assert_eq!(&smaller, b"12345");
+
+        let (chunk, len) = fs.read_chunk::<consts::U4>(path)?;
+        assert_eq!(len, 4);
+        assert_eq!(chunk.as_slice(), b"1234");
+        let (chunk, len) = fs.read_chunk::<consts::U16>(path)?;
+        assert_eq!(len, 8);
+        assert_eq!(&chunk[..len], b"12345678");
+
+        assert_eq!(fs.read_into(b"/missing\0".try_into().unwrap(), &mut exact), Err(Error::NoSuchEntry));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn inline_max() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        // the cache size
+        assert_eq!(fs.inline_max(), 32);
+
+        fs.create_dir(b"/fido\0".try_into().unwrap())?;
+        fs.create_dir(b"/fido/sec\0".try_into().unwrap())?;
+        let inline = b"/fido/sec/inline\0".try_into().unwrap();
+        let outlined = b"/fido/sec/outlined\0".try_into().unwrap();
+        fs.write(inline, &[1; 32])?;
+        fs.write(outlined, &[2; 33])?;
+        assert_eq!(fs.file_block_count(inline)?, 0);
+        assert_eq!(fs.file_block_count(outlined)?, 1);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn copy_dir_all() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let files: [(&str, &[u8]); 3] = [
+            ("state", b"state"),
+            ("keys/sign", b"sign key"),
+            ("keys/auth", &[0xaa; 300]),
+        ];
+        fs.create_dir(b"/opcard\0".try_into().unwrap())?;
+        fs.create_dir(b"/opcard/keys\0".try_into().unwrap())?;
+        fs.create_dir(b"/opcard/empty\0".try_into().unwrap())?;
+        for (name, contents) in files.iter() {
+            fs.write(&PathBuf::from("/opcard").join(&PathBuf::from(*name)), contents)?;
+        }
+
+        let opcard: &Path = b"/opcard\0".try_into().unwrap();
+        let backup: &Path = b"/backup/opcard\0".try_into().unwrap();
+        fs.copy_dir_all(opcard, backup)?;
+        for (name, contents) in files.iter() {
+            let copied: heapless::Vec<_, 300> = fs.read(&backup.join(&PathBuf::from(*name)))?;
+            assert_eq!(copied, *contents);
+        }
+        assert!(fs.is_dir(b"/backup/opcard/empty\0".try_into().unwrap()));
+        assert_eq!(fs.dir_size(backup)?, fs.dir_size(opcard)?);
+
+        // into an existing directory, overwriting files
+        fs.write(b"/opcard/state\0".try_into().unwrap(), b"new state")?;
+        fs.copy_dir_all(opcard, backup)?;
+        let state: heapless::Vec<_, 16> = fs.read(b"/backup/opcard/state\0".try_into().unwrap())?;
+        assert_eq!(state, b"new state");
+
+        assert_eq!(fs.copy_dir_all(b"/opcard/state\0".try_into().unwrap(), backup), Err(Error::PathNotDir));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn dir_size() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/opcard\0".try_into().unwrap())?;
+        fs.create_dir(b"/fido\0".try_into().unwrap())?;
+        fs.create_dir(b"/fido/rk\0".try_into().unwrap())?;
+        fs.write(b"/opcard/state\0".try_into().unwrap(), &[1; 100])?;
+        fs.write(b"/fido/counter\0".try_into().unwrap(), &[2; 36])?;
+        fs.write(b"/fido/rk/cred\0".try_into().unwrap(), &[3; 37])?;
+
+        assert_eq!(fs.dir_size(b"/fido\0".try_into().unwrap())?, 36 + 37);
+        assert_eq!(fs.dir_size(b"/\0".try_into().unwrap())?, 100 + 36 + 37);
+        assert_eq!(fs.dir_size(b"/fido/rk\0".try_into().unwrap())?, 37);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn enforce_budget() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let logs = b"/logs\0".try_into().unwrap();
+        fs.create_dir(logs)?;
+
+        // names deliberately out of order with the sequence numbers
+        let names = ["/logs/f", "/logs/b", "/logs/e", "/logs/a", "/logs/d", "/logs/c"];
+        for (sequence, name) in (1u32..).zip(names.iter()) {
+            let path = PathBuf::from(*name);
+            fs.write(&path, &[sequence as u8; 100])?;
+            let mut attribute = Attribute::new(crate::consts::SEQUENCE_ATTRIBUTE_ID);
+            fs.set_attribute(&path, attribute.set_data(&sequence.to_le_bytes()))?;
+        }
+
+        // within budget, nothing to do
+        assert_eq!(fs.enforce_budget(logs, 600)?, 0);
+
+        assert_eq!(fs.enforce_budget(logs, 350)?, 300);
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(PathBuf::from(*name).exists(fs), i >= 3);
+        }
+        Ok(())
+    }).unwrap();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn std_io() {
+    use std::io::{BufRead, Seek, Write};
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/lines.txt\0".try_into().unwrap();
+        fs.create_file_and_then(path, |mut file| {
+            writeln!(file, "first").unwrap();
+            writeln!(file, "second").unwrap();
+            file.flush().unwrap();
+            Ok(())
+        })?;
+
+        fs.open_file_and_then(path, |mut file| {
+            let lines: Vec<String> = std::io::BufReader::new(file)
+                .lines()
+                .collect::<std::io::Result<_>>()
+                .unwrap();
+            assert_eq!(lines, ["first", "second"]);
+
+            Seek::seek(&mut file, std::io::SeekFrom::Start(6)).unwrap();
+            let mut copied = Vec::new();
+            std::io::copy(&mut file, &mut copied).unwrap();
+            assert_eq!(copied, b"second\n");
+            Ok(())
+        })
+    }).unwrap();
+}
+
+#[test]
+fn is_mountable() {
+    let mut backend = OtherRam::default();
+    backend.buf.iter_mut().for_each(|byte| *byte = 0);
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    assert!(!Filesystem::is_mountable(&mut storage));
+    assert!(storage.backend.buf.iter().all(|byte| *byte == 0));
+
+    Filesystem::format(&mut storage).unwrap();
+    let formatted = storage.backend.buf.to_vec();
+    assert!(Filesystem::is_mountable(&mut storage));
+    assert_eq!(&storage.backend.buf[..], &formatted[..]);
+}
+
+#[test]
+fn mount_or_format() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    let path: &Path = b"/boot-count\0".try_into().unwrap();
+
+    for boot in 1..=2u8 {
+        let mut alloc = Filesystem::allocate();
+        let fs = Filesystem::mount_or_format(&mut alloc, &mut storage).unwrap();
+        if boot == 1 {
+            assert!(!path.exists(&fs));
+            fs.write(path, &[boot]).unwrap();
+        } else {
+            let contents: heapless::Vec<_, 1> = fs.read(path).unwrap();
+            assert_eq!(contents, [1]);
+        }
+    }
+}
+
+#[test]
+fn copy() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let config: &Path = b"/config\0".try_into().unwrap();
+        let backup: &Path = b"/config.bak\0".try_into().unwrap();
+        let missing: &Path = b"/missing\0".try_into().unwrap();
+
+        let mut contents = [0u8; 2378];
+        for (i, byte) in contents.iter_mut().enumerate() {
+            *byte = (i % 251) as u8;
+        }
+        fs.write(config, &contents)?;
+
+        // a previous, longer backup gets truncated
+        fs.write(backup, &[0xaa; 3000])?;
+        assert_eq!(fs.copy(config, backup)?, contents.len() as u64);
+        let copied: heapless::Vec<_, 4096> = fs.read(backup)?;
+        assert_eq!(&copied[..], &contents[..]);
+
+        assert_eq!(fs.copy(missing, b"/copy\0".try_into().unwrap()), Err(Error::NoSuchEntry));
+        assert!(!PathBuf::from(b"/copy").exists(fs));
+        assert_eq!(fs.copy(config, config), Err(Error::Invalid));
+
+        // more than half of the storage does not fit twice
+        let large: &Path = b"/large\0".try_into().unwrap();
+        fs.create_file_and_then(large, |file| {
+            for _ in 0..100 {
+                crate::io::Write::write_all(file, &[0x55; 700])?;
+            }
+            Ok(())
+        })?;
+        let copy: &Path = b"/copy\0".try_into().unwrap();
+        assert_eq!(fs.copy(large, copy), Err(Error::NoSpace));
+        assert!(!copy.exists(fs));
+        // only files created by the copy are removed
+        assert_eq!(fs.copy(large, backup), Err(Error::NoSpace));
+        assert!(backup.exists(fs));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn dir_is_empty() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let dir: &Path = b"/dir\0".try_into().unwrap();
+        let file: &Path = b"/dir/file\0".try_into().unwrap();
+        fs.create_dir(dir)?;
+        assert!(fs.dir_is_empty(dir)?);
+
+        fs.write(file, b"data")?;
+        assert!(!fs.dir_is_empty(dir)?);
+
+        assert_eq!(fs.dir_is_empty(file), Err(Error::PathNotDir));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn dir_entry_count() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let dir: &Path = b"/dir\0".try_into().unwrap();
+        let empty: &Path = b"/empty\0".try_into().unwrap();
+        fs.create_dir(dir)?;
+        fs.create_dir(empty)?;
+        assert_eq!(fs.dir_entry_count(empty)?, 0);
+
+        for i in 0..5u8 {
+            fs.write(&dir.join(&PathBuf::from(&[b'f', b'0' + i][..])), &[i])?;
+        }
+        assert_eq!(fs.dir_entry_count(dir)?, 5);
+        assert_eq!(fs.dir_entry_count(b"/\0".try_into().unwrap())?, 2);
+
+        assert_eq!(fs.dir_entry_count(b"/dir/f0\0".try_into().unwrap()), Err(Error::PathNotDir));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn read_dir_matching() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let dir: &Path = b"/keys\0".try_into().unwrap();
+        fs.create_dir(dir)?;
+        for name in ["a.key", "b.key", "c.pub", "key"].iter() {
+            fs.write(&dir.join(&PathBuf::from(*name)), b"data")?;
+        }
+
+        let mut matching: heapless::Vec<PathBuf, 4> = heapless::Vec::new();
+        fs.read_dir_matching_and_then(dir, "*.key", |entries| {
+            for entry in entries {
+                matching.push(entry?.file_name().into()).unwrap();
+            }
+            Ok(())
+        })?;
+        assert_eq!(matching, [PathBuf::from("a.key"), PathBuf::from("b.key")]);
+
+        let count = fs.read_dir_matching_and_then(dir, "?", |entries| Ok(entries.count()))?;
+        // only `.`
+        assert_eq!(count, 1);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn read_dir_filter() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/dir\0".try_into().unwrap())?;
+        fs.create_dir(b"/dir/a\0".try_into().unwrap())?;
+        fs.create_dir(b"/dir/b\0".try_into().unwrap())?;
+        fs.write(b"/dir/file\0".try_into().unwrap(), b"data")?;
+
+        let dirs = fs.read_dir_and_then(b"/dir\0".try_into().unwrap(), |read_dir| {
+            Ok(read_dir
+                .filter(|entry| entry.as_ref().map_or(true, |entry| entry.file_type().is_dir()))
+                .count())
+        })?;
+        // including `.` and `..`
+        assert_eq!(dirs, 4);
+
+        // stopping early is fine, the directory is closed all the same
+        let first = fs.read_dir_and_then(b"/dir\0".try_into().unwrap(), |read_dir| {
+            read_dir.find(|entry| entry.as_ref().map_or(true, |entry| entry.file_type().is_dir())).unwrap()
+        })?;
+        assert_eq!(first.file_name(), ".");
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn read_dir_file_types() {
+    use crate::fs::FileType;
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/dir\0".try_into().unwrap())?;
+        fs.create_dir(b"/dir/sub\0".try_into().unwrap())?;
+        fs.write(b"/dir/file\0".try_into().unwrap(), b"data")?;
+
+        fs.read_dir_and_then(b"/dir\0".try_into().unwrap(), |read_dir| {
+            for entry in read_dir {
+                let entry = entry?;
+                let expected = match entry.file_name().as_ref() {
+                    "." | ".." | "sub" => FileType::Dir,
+                    "file" => FileType::File,
+                    name => panic!("unexpected entry {}", name),
+                };
+                assert_eq!(entry.file_type(), expected);
+                assert_eq!(entry.metadata().file_type(), expected);
+                assert_eq!(entry.metadata().is_dir(), expected.is_dir());
+            }
+            Ok(())
+        })
+    }).unwrap();
+}
+
+#[test]
+fn path_predicates() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let dir: &Path = b"/dir\0".try_into().unwrap();
+        let file: &Path = b"/dir/file\0".try_into().unwrap();
+        let missing: &Path = b"/dir/missing\0".try_into().unwrap();
+        fs.create_dir(dir)?;
+        fs.write(file, b"data")?;
+
+        assert!(fs.exists(file) && fs.is_file(file) && !fs.is_dir(file));
+        assert!(fs.exists(dir) && fs.is_dir(dir) && !fs.is_file(dir));
+        assert!(!fs.exists(missing) && !fs.is_file(missing) && !fs.is_dir(missing));
+
+        assert_eq!(fs.try_exists(file), Ok(true));
+        assert_eq!(fs.try_exists(missing), Ok(false));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn modified() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let stamped: &Path = b"/stamped\0".try_into().unwrap();
+        let unstamped: &Path = b"/unstamped\0".try_into().unwrap();
+        fs.write(stamped, b"data")?;
+        fs.write(unstamped, b"data")?;
+
+        fs.set_modified(stamped, 1_700_000_000)?;
+        assert_eq!(fs.modified(stamped)?, Some(1_700_000_000));
+        fs.set_modified(stamped, u64::MAX)?;
+        assert_eq!(fs.modified(stamped)?, Some(u64::MAX));
+
+        assert_eq!(fs.modified(unstamped)?, None);
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn for_each_chunk() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path: &Path = b"/large\0".try_into().unwrap();
+        let mut contents = [0u8; 10 * 1024];
+        for (i, byte) in contents.iter_mut().enumerate() {
+            *byte = (i % 253) as u8;
+        }
+        fs.write(path, &contents)?;
+
+        fs.open_file_and_then(path, |file| {
+            let mut streamed = Vec::new();
+            let mut chunks = 0;
+            // OtherRam's cache is 32 bytes, so larger chunks are capped
+            file.for_each_chunk(100, |chunk| {
+                assert!(chunk.len() <= 32);
+                streamed.extend_from_slice(chunk);
+                chunks += 1;
+                Ok(())
+            })?;
+            assert_eq!(&streamed[..], &contents[..]);
+            assert_eq!(chunks, 10 * 1024 / 32);
+
+            file.seek(SeekFrom::Start(10 * 1024 - 20))?;
+            let mut lengths = Vec::new();
+            file.for_each_chunk(8, |chunk| {
+                lengths.push(chunk.len());
+                Ok(())
+            })?;
+            assert_eq!(lengths, [8, 8, 4]);
+
+            assert_eq!(file.for_each_chunk(0, |_| Ok(())), Err(Error::Invalid));
+            Ok(())
+        })
+    }).unwrap();
+}
+
+#[test]
+fn available_space() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(fs.total_space(), 512 * 256);
+
+        let before = fs.available_space()?;
+        assert!(before < fs.total_space());
+        fs.write(b"/large\0".try_into().unwrap(), &[0x55; 4096])?;
+        let after = fs.available_space()?;
+        assert!(after + 4096 <= before);
+        assert_eq!(after % 256, 0);
+        Ok(())
+    }).unwrap();
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn vec_ram_storage() {
+    vec_ram_storage!(
+        name=VecStorage,
+        trait=driver::Storage,
+        erase_value=0xff,
+        read_size=16,
+        write_size=16,
+        cache_size_ty=consts::U256,
+        block_size=512,
+        lookaheadwords_size_ty=consts::U1,
+        filename_max_plus_one_ty=consts::U256,
+        path_max_plus_one_ty=consts::U256,
+        result=Result,
+    );
+
+    fn format_and_fill(blocks: usize) {
+        let mut storage = VecStorage::new(blocks);
+        Filesystem::format(&mut storage).unwrap();
+        Filesystem::mount_and_then(&mut storage, |fs| {
+            assert_eq!(fs.total_blocks(), blocks);
+            fs.write(b"/data\0".try_into().unwrap(), &[0x42; 2048])?;
+            let contents: heapless::Vec<_, 2048> = fs.read(b"/data\0".try_into().unwrap())?;
+            assert_eq!(&contents[..], &[0x42; 2048][..]);
+            Ok(())
+        }).unwrap();
+    }
+
+    format_and_fill(16);
+    format_and_fill(1024);
+
+    // wrappers check the size at runtime, not just `BLOCK_COUNT`
+    let mut storage = VecStorage::new(64);
+    let mut partition = driver::PartitionStorage::<_, 32, 32>::new(&mut storage);
+    Filesystem::format(&mut partition).unwrap();
+    let too_large = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        driver::PartitionStorage::<_, 32, 64>::new(&mut storage);
+    }));
+    assert!(too_large.is_err());
+    let remap = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        driver::RemapStorage::<_, 2>::new(&mut storage).ok();
+    }));
+    assert!(remap.is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn file_storage() {
+    file_storage!(
+        name=FileStorage,
+        trait=driver::Storage,
+        erase_value=0xff,
+        read_size=16,
+        write_size=16,
+        cache_size_ty=consts::U256,
+        block_size=512,
+        block_count=64,
+        lookaheadwords_size_ty=consts::U2,
+        filename_max_plus_one_ty=consts::U256,
+        path_max_plus_one_ty=consts::U256,
+        result=Result,
+    );
+
+    let image = std::env::temp_dir().join(format!("littlefs2-test-{}.img", std::process::id()));
+    let open = || {
+        std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&image).unwrap()
+    };
+    let path = b"/hello.txt\0".try_into().unwrap();
+
+    let mut storage = FileStorage::new(open());
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| fs.write(path, b"from the host")).unwrap();
+    storage.into_inner().sync_all().unwrap();
+
+    let mut storage = FileStorage::new(open());
+    let contents: heapless::Vec<_, 32> = Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.read(path)
+    }).unwrap();
+    assert_eq!(contents, b"from the host");
+
+    drop(storage);
+    std::fs::remove_file(&image).unwrap();
+}
+
+#[cfg(feature = "defmt")]
+#[test]
+fn defmt_format() {
+    fn is_format<T: defmt::Format>() {}
+    is_format::<Error>();
+    is_format::<crate::fs::FileType>();
+    is_format::<crate::fs::Metadata>();
+    is_format::<crate::fs::OpenOptions>();
+}
+
+#[cfg(feature = "log-all")]
+#[test]
+fn log_storage_accesses() {
+    use std::sync::Mutex;
+
+    // the logger is global, so it also collects the messages of other tests
+    struct Capture(Mutex<std::vec::Vec<std::string::String>>);
+
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(std::vec::Vec::new()));
+    log::set_logger(&CAPTURE).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(b"/logged\0".try_into().unwrap(), b"logged")
+    }).unwrap();
+
+    let messages = CAPTURE.0.lock().unwrap();
+    assert!(messages.iter().any(|message| message.starts_with("read block ")));
+    assert!(messages.iter().any(|message| message.starts_with("erase block ")));
+}
+
+#[test]
+fn recursion_depth_is_bounded() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let mut deep = PathBuf::from("/d");
+        fs.create_dir(&deep)?;
+        for _ in 1..64 {
+            deep.push(b"d\0".try_into().unwrap());
+            fs.create_dir(&deep)?;
+        }
+        fs.write(&deep.join(b"file\0".try_into().unwrap()), b"at the bottom")?;
+
+        let root = b"/d\0".try_into().unwrap();
+        assert_eq!(fs.walk_dir(root, |_, _| Ok(())), Err(Error::DirectoryTooDeep));
+        assert_eq!(fs.find(root, |_, metadata| metadata.is_file()), Err(Error::DirectoryTooDeep));
+        #[cfg(feature = "dir-entry-path")]
+        assert_eq!(fs.remove_dir_all(root), Err(Error::DirectoryTooDeep));
+        // nothing was removed, the tree is a single chain
+        assert!(fs.exists(&deep));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn compare_dir_entries() {
+    use crate::fs::{DirEntry, FileType};
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(b"/b.txt\0".try_into().unwrap(), b"bee")?;
+        fs.create_dir(b"/c\0".try_into().unwrap())?;
+        fs.write(b"/a.txt\0".try_into().unwrap(), b"a")?;
+
+        let list = || -> Result<Vec<DirEntry>> {
+            let mut entries = fs.read_dir_and_then(b"/\0".try_into().unwrap(), |read_dir| {
+                read_dir.skip(2).collect::<Result<Vec<_>>>()
+            })?;
+            entries.sort_by(|a, b| a.file_name().as_ref().cmp(b.file_name().as_ref()));
+            Ok(entries)
+        };
+        let entries = list()?;
+
+        let expected = [("a.txt", FileType::File, 1), ("b.txt", FileType::File, 3), ("c", FileType::Dir, 0)];
+        assert_eq!(entries.len(), expected.len());
+        for (entry, (name, file_type, len)) in entries.iter().zip(expected.iter()) {
+            assert_eq!(entry.file_name(), *name);
+            assert_eq!(entry.file_type(), *file_type);
+            assert_eq!(entry.metadata(), fs.metadata(&PathBuf::from("/").join(entry.file_name()))?);
+            if file_type.is_file() {
+                assert_eq!(entry.metadata().len(), *len);
+            }
+        }
+        // listing again yields equal entries
+        assert_eq!(entries, list()?);
+
+        let debug = format!("{:?}", entries[1].metadata());
+        assert!(debug.contains("File") && debug.contains("size: 3"));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn sorted_listing() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        for name in ["/zeta", "/Beta", "/alpha", "/a-b", "/alpha2", "/10", "/9"].iter() {
+            fs.write(&PathBuf::from(*name), b"")?;
+        }
+        fs.create_dir(b"/a\0".try_into().unwrap())?;
+
+        let mut names: Vec<PathBuf> = fs.read_dir_and_then(b"/\0".try_into().unwrap(), |read_dir| {
+            read_dir.skip(2).map(|entry| entry.map(|entry| PathBuf::from(entry.file_name()))).collect()
+        })?;
+        names.sort();
+
+        // bytewise: digits, then upper case, then lower case
+        let expected = ["10", "9", "Beta", "a", "a-b", "alpha", "alpha2", "zeta"];
+        assert_eq!(names.len(), expected.len());
+        for (name, expected) in names.iter().zip(expected.iter()) {
+            assert_eq!(name.as_ref(), *expected);
+        }
+
+        let unique: std::collections::HashSet<_> = names.iter().cloned().collect();
+        assert_eq!(unique.len(), names.len());
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn iterate_attributes() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/file\0".try_into().unwrap();
+        fs.write(path, b"contents")?;
+        assert_eq!(fs.attributes(path)?.count(), 0);
+
+        for id in [1u8, 5, 200].iter() {
+            let mut attribute = Attribute::new(*id);
+            fs.set_attribute(path, attribute.set_data(&[*id; 3]))?;
+        }
+        let attributes = fs.attributes(path)?.collect::<Result<Vec<_>>>()?;
+        let ids: Vec<u8> = attributes.iter().map(Attribute::id).collect();
+        assert_eq!(ids, [1, 5, 200]);
+        for attribute in attributes.iter() {
+            assert_eq!(attribute.data(), [attribute.id(); 3]);
+        }
+
+        assert!(fs.attributes(b"/missing\0".try_into().unwrap()).is_err());
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn clear_attributes() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/file\0".try_into().unwrap();
+        fs.write(path, b"contents")?;
+        fs.clear_attributes(path)?;
+
+        for id in [1u8, 5, 200].iter() {
+            let mut attribute = Attribute::new(*id);
+            fs.set_attribute(path, attribute.set_data(b"data"))?;
+        }
+        fs.clear_attributes(path)?;
+        for id in [1u8, 5, 200].iter() {
+            assert!(fs.attribute(path, *id)?.is_none());
+        }
+        let contents: heapless::Vec<_, 16> = fs.read(path)?;
+        assert_eq!(contents, b"contents");
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn read_uninit() {
+    use core::mem::MaybeUninit;
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/file\0".try_into().unwrap();
+        let data: Vec<u8> = (0..3000u32).map(|i| i as u8).collect();
+        fs.write(path, &data)?;
+        fs.open_file_and_then(path, |file| {
+            let mut buf = [MaybeUninit::<u8>::uninit(); 4096];
+            let read = file.read_uninit(&mut buf)?;
+            assert_eq!(read, &data[..]);
+            assert_eq!(file.read_uninit(&mut buf)?.len(), 0);
+            Ok(())
+        })
+    }).unwrap();
+}
+
+#[test]
+fn position() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/file\0".try_into().unwrap();
+        fs.open_file_with_options_and_then(
+            |options| options.read(true).write(true).create(true),
+            path,
+            |file| {
+                assert_eq!(file.position()?, 0);
+                file.write(&[0x11; 100])?;
+                assert_eq!(file.position()?, 100);
+                file.seek(SeekFrom::Start(50))?;
+                assert_eq!(file.position()?, 50);
+                let mut buf = [0u8; 10];
+                assert_eq!(file.read(&mut buf)?, 10);
+                assert_eq!(file.position()?, 60);
+                Ok(())
+            },
+        )?;
+
+        fs.open_file_with_options_and_then(
+            |options| options.read(true).write(true).append(true),
+            path,
+            |file| {
+                file.seek(SeekFrom::Start(10))?;
+                assert_eq!(file.position()?, 10);
+                file.write(&[0x22; 20])?;
+                assert_eq!(file.position()?, 120);
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn rewind() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/file\0".try_into().unwrap();
+        fs.write(path, b"some contents")?;
+        fs.open_file_and_then(path, |file| {
+            let mut first = heapless::Vec::<u8, 32>::new();
+            file.read_to_end(&mut first)?;
+            file.rewind()?;
+            let mut second = heapless::Vec::<u8, 32>::new();
+            file.read_to_end(&mut second)?;
+            assert_eq!(first, b"some contents");
+            assert_eq!(first, second);
+            Ok(())
+        })?;
+
+        // buffered writes survive rewinding
+        fs.open_file_with_options_and_then(
+            |options| options.read(true).write(true),
+            path,
+            |file| {
+                file.seek(SeekFrom::End(0))?;
+                file.write(b", more")?;
+                file.rewind()?;
+                let mut contents = heapless::Vec::<u8, 32>::new();
+                file.read_to_end(&mut contents)?;
+                assert_eq!(contents, b"some contents, more");
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }).unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_listing() {
+    use crate::fs::DirEntry;
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(b"/fido\0".try_into().unwrap())?;
+        fs.write(b"/fido/counter\0".try_into().unwrap(), &[0; 36])?;
+        fs.create_dir(b"/fido/rk\0".try_into().unwrap())?;
+
+        let listing: Vec<DirEntry> = fs.read_dir_and_then(b"/fido\0".try_into().unwrap(), |read_dir| {
+            read_dir.skip(2).collect()
+        })?;
+        let json = serde_json::to_string(&listing).unwrap();
+        assert!(json.contains(r#""file_name":"counter""#));
+        assert!(json.contains(r#""len":36"#));
+        let decoded: Vec<DirEntry> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, listing);
+
+        let path: &Path = b"/fido/rk\0".try_into().unwrap();
+        assert_eq!(serde_json::to_string(path).unwrap(), r#""/fido/rk""#);
+        let decoded: PathBuf = serde_json::from_str(r#""/fido/rk""#).unwrap();
+        assert_eq!(decoded, PathBuf::from(path));
+        assert!(serde_json::from_str::<PathBuf>(r#""/a\u0000b""#).is_err());
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn size_seek() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/abc\0".try_into().unwrap();
+        fs.write(path, b"abcdef")?;
+        fs.open_file_and_then(path, |file| {
+            let mut buf = [0u8; 2];
+            file.read(&mut buf)?;
+            assert_eq!(file.size_seek()?, 6);
+
+            let mut next = [0u8; 1];
+            assert_eq!(file.read(&mut next)?, 1);
+            assert_eq!(&next, b"c");
+            Ok(())
+        })
+    }).unwrap();
+}
+
+#[test]
+fn concurrent_handles() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = b"/shared\0".try_into().unwrap();
+        let mut contents = [0u8; 200];
+        for (i, byte) in contents.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        fs.write(path, &contents)?;
+
+        // two read cursors, interleaved over disjoint regions
+        fs.open_file_and_then(path, |front| {
+            fs.open_file_and_then(path, |back| {
+                back.seek(SeekFrom::Start(100))?;
+                let mut a = [0u8; 10];
+                let mut b = [0u8; 10];
+                for i in 0..10 {
+                    front.read(&mut a)?;
+                    back.read(&mut b)?;
+                    assert_eq!(&a[..], &contents[10 * i..][..10]);
+                    assert_eq!(&b[..], &contents[100 + 10 * i..][..10]);
+                }
+                Ok(())
+            })
+        })?;
+
+        // a second writer is refused, without committing its truncation
+        fs.open_file_with_options_and_then(|options| options.write(true), path, |first| {
+            let second = fs.open_file_with_options_and_then(
+                |options| options.write(true).truncate(true),
+                path,
+                |_| Ok(()),
+            );
+            assert_eq!(second, Err(Error::FileBusy));
+            assert_eq!(fs.open_file_count(), 1);
+            // readers are fine
+            fs.open_file_and_then(path, |_| Ok(()))?;
+            first.write(b"FIRST")?;
+            Ok(())
+        })?;
+        let mut expected = contents;
+        expected[..5].copy_from_slice(b"FIRST");
+        let read: heapless::Vec<_, 256> = fs.read(path)?;
+        assert_eq!(&read[..], &expected[..]);
+
+        // once the writer is closed, the next one may open
+        fs.open_file_with_options_and_then(|options| options.write(true), path, |_| Ok(()))?;
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn format_with_options() {
+    use crate::fs::FormatOptions;
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    let invalid = FormatOptions::new().block_cycles(0);
+    assert_eq!(Filesystem::format_with_options(&mut storage, invalid), Err(Error::Invalid));
+    assert!(!Filesystem::is_mountable(&mut storage));
+
+    Filesystem::format_with_options(&mut storage, FormatOptions::new().block_cycles(100)).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(fs.disk_version(), 0x0002_0000);
+        fs.write(b"/file\0".try_into().unwrap(), b"data")
+    }).unwrap();
+}
+
+#[test]
+fn format_verify_erase() {
+    use crate::fs::FormatOptions;
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    // the storage erases to 0xff
+    let mismatch = FormatOptions::new().verify_erase(0x00);
+    assert_eq!(Filesystem::format_with_options(&mut storage, mismatch), Err(Error::Io));
+    assert!(!Filesystem::is_mountable(&mut storage));
+
+    Filesystem::format_with_options(&mut storage, FormatOptions::new().verify_erase(0xff)).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(b"/file\0".try_into().unwrap(), b"data")
+    }).unwrap();
+}
+
+#[test]
+fn full_lookahead() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        // 32 bytes of lookahead cover 256 blocks, these files need more than that,
+        // so littlefs fills the whole lookahead buffer and scans again
+        for i in 0..24u8 {
+            let path = PathBuf::from(&[b'/', b'a' + i][..]);
+            fs.write(&path, &[i; 3000])?;
+        }
+        for i in 0..24u8 {
+            let path = PathBuf::from(&[b'/', b'a' + i][..]);
+            let contents: heapless::Vec<_, 3000> = fs.read(&path)?;
+            assert_eq!(contents, &[i; 3000][..]);
+        }
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn mount_with_options() {
+    use crate::fs::MountOptions;
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+
+    let invalid = MountOptions::new().block_cycles(-2);
+    let result = Filesystem::mount_and_then_with_options(&mut storage, invalid, |_| Ok(()));
+    assert_eq!(result, Err(Error::Invalid));
+
+    // aggressive wear-leveling, blocks move on almost every rewrite
+    let options = MountOptions::new().block_cycles(1);
+    Filesystem::mount_and_then_with_options(&mut storage, options, |fs| {
+        let path = b"/counter\0".try_into().unwrap();
+        for i in 0..20u8 {
+            fs.write(path, &[i; 300])?;
+            let contents: heapless::Vec<_, 300> = fs.read(path)?;
+            assert_eq!(contents, &[i; 300][..]);
+        }
+        Ok(())
+    }).unwrap();
+
+    let mut alloc = Filesystem::allocate();
+    let fs = Filesystem::mount_with_options(&mut alloc, &mut storage, MountOptions::new().block_cycles(-1)).unwrap();
+    let contents: heapless::Vec<_, 300> = fs.read(b"/counter\0".try_into().unwrap()).unwrap();
+    assert_eq!(contents, &[19; 300][..]);
+    fs.unmount().unwrap();
+}
+
+#[test]
+fn mount_with_lookahead_size() {
+    use crate::fs::MountOptions;
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+
+    // one lookahead word, 32 bytes
+    for &invalid in [0, 12, 40].iter() {
+        let options = MountOptions::new().lookahead_size(invalid);
+        let result = Filesystem::mount_and_then_with_options(&mut storage, options, |_| Ok(()));
+        assert_eq!(result, Err(Error::Invalid));
+    }
+
+    // more blocks than a lookahead of 8 bytes covers, so it is refilled several times
+    for &lookahead_size in [8, 32].iter() {
+        let options = MountOptions::new().lookahead_size(lookahead_size);
+        Filesystem::mount_and_then_with_options(&mut storage, options, |fs| {
+            let data = [lookahead_size as u8; 2000];
+            for i in 0..20u8 {
+                fs.write(&PathBuf::from(&[b'/', b'a' + i][..]), &data)?;
+            }
+            for i in 0..20u8 {
+                let contents: heapless::Vec<_, 2000> = fs.read(&PathBuf::from(&[b'/', b'a' + i][..]))?;
+                assert_eq!(contents, &data[..]);
+            }
+            Ok(())
+        }).unwrap();
+    }
+}
+
+#[test]
+fn storage_requirements() {
+    use crate::fs::{check_storage_requirements, ConfigError};
+    use core::marker::PhantomData;
+    use generic_array::ArrayLength;
+
+    // never touched, the geometry is rejected first
+    struct Geometry<
+        const READ: usize, const WRITE: usize, const BLOCK: usize, const COUNT: usize,
+        const CYCLES: isize, Cache, Lookahead,
+    >(PhantomData<(Cache, Lookahead)>);
+
+    impl<
+        const READ: usize, const WRITE: usize, const BLOCK: usize, const COUNT: usize,
+        const CYCLES: isize, Cache: ArrayLength<u8>, Lookahead: ArrayLength<u32>,
+    > driver::Storage for Geometry<READ, WRITE, BLOCK, COUNT, CYCLES, Cache, Lookahead> {
+        const READ_SIZE: usize = READ;
+        const WRITE_SIZE: usize = WRITE;
+        const BLOCK_SIZE: usize = BLOCK;
+        const BLOCK_COUNT: usize = COUNT;
+        const BLOCK_CYCLES: isize = CYCLES;
+        type CACHE_SIZE = Cache;
+        type LOOKAHEADWORDS_SIZE = Lookahead;
+
+        fn read(&self, _: usize, _: &mut [u8]) -> Result<usize> { unreachable!() }
+        fn write(&mut self, _: usize, _: &[u8]) -> Result<usize> { unreachable!() }
+        fn erase(&mut self, _: usize, _: usize) -> Result<usize> { unreachable!() }
+    }
+
+    use consts::{U0, U1, U48, U64};
+    assert_eq!(check_storage_requirements::<Geometry<16, 16, 512, 8, -1, U64, U1>>(), Ok(()));
+    assert_eq!(check_storage_requirements::<Geometry<16, 16, 512, 8, 100, U64, U1>>(), Ok(()));
+
+    let expected = [
+        (check_storage_requirements::<Geometry<16, 16, 512, 8, 0, U64, U1>>(), ConfigError::InvalidBlockCycles),
+        (check_storage_requirements::<Geometry<16, 16, 512, 8, -2, U64, U1>>(), ConfigError::InvalidBlockCycles),
+        (check_storage_requirements::<Geometry<16, 16, 512, 0, -1, U64, U1>>(), ConfigError::ZeroBlockCount),
+        (check_storage_requirements::<Geometry<0, 16, 512, 8, -1, U64, U1>>(), ConfigError::ZeroReadSize),
+        (check_storage_requirements::<Geometry<16, 0, 512, 8, -1, U64, U1>>(), ConfigError::ZeroWriteSize),
+        (check_storage_requirements::<Geometry<16, 16, 64, 8, -1, U64, U1>>(), ConfigError::BlockTooSmall),
+        (check_storage_requirements::<Geometry<16, 16, 512, 8, -1, U0, U1>>(), ConfigError::ZeroCacheSize),
+        (check_storage_requirements::<Geometry<16, 16, 512, 8, -1, U64, U0>>(), ConfigError::ZeroLookahead),
+        (check_storage_requirements::<Geometry<20, 16, 512, 8, -1, U64, U1>>(), ConfigError::CacheNotMultipleOfRead),
+        (check_storage_requirements::<Geometry<16, 32, 512, 8, -1, U48, U1>>(), ConfigError::CacheNotMultipleOfWrite),
+        (check_storage_requirements::<Geometry<16, 16, 520, 8, -1, U64, U1>>(), ConfigError::BlockNotMultipleOfCache),
+    ];
+    for (result, error) in expected.iter() {
+        assert_eq!(result, &Err(*error));
+    }
+
+    let mut misconfigured = Geometry::<16, 16, 512, 8, -1, U48, U1>(PhantomData);
+    assert_eq!(
+        Filesystem::try_format(&mut misconfigured),
+        Err(Error::InvalidConfig(ConfigError::BlockNotMultipleOfCache)),
+    );
+    assert!(Filesystem::try_mount_and_then(&mut misconfigured, |_| Ok(())).is_err());
+
+    // valid geometries also pass the compile-time check
+    const _: () = crate::fs::assert_storage_requirements::<Geometry<16, 16, 512, 8, -1, U64, U1>>();
+    const _: () = crate::fs::assert_storage_requirements::<OtherRamStorage<'static>>();
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::try_format(&mut storage).unwrap();
+    Filesystem::try_mount_and_then(&mut storage, |fs| fs.write(b"/ok\0".try_into().unwrap(), b"ok")).unwrap();
+}
+
+#[test]
+fn size_limits() {
+    use crate::fs::{check_limits, ConfigError};
+
+    assert_eq!(check_limits(256, 2_147_483_647, 1_022), Ok(()));
+    assert_eq!(check_limits(1, 2_147_483_647, 1_022), Err(ConfigError::InvalidNameMax));
+    assert_eq!(check_limits(1_024, 2_147_483_647, 1_022), Err(ConfigError::InvalidNameMax));
+    assert_eq!(check_limits(256, 0, 1_022), Err(ConfigError::InvalidFileMax));
+    assert_eq!(check_limits(256, 2_147_483_648, 1_022), Err(ConfigError::InvalidFileMax));
+    assert_eq!(check_limits(256, 2_147_483_647, 0), Err(ConfigError::InvalidAttrMax));
+    assert_eq!(check_limits(256, 2_147_483_647, 1_023), Err(ConfigError::InvalidAttrMax));
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::try_format(&mut storage).unwrap();
+    let mut alloc = crate::fs::Allocation::try_new().unwrap();
+    let fs = Filesystem::try_mount(&mut alloc, &mut storage).unwrap();
+    fs.write(b"/ok\0".try_into().unwrap(), b"ok").unwrap();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn sync_filesystem() {
+    use crate::fs::SyncFilesystem;
+    use std::sync::Arc;
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    let mut alloc = Filesystem::allocate();
+    let fs = Filesystem::mount(&mut alloc, &mut storage).unwrap();
+
+    let shared = Arc::new(SyncFilesystem::new(fs));
+    shared.write(b"/served\0".try_into().unwrap(), &[0x5a; 1000]).unwrap();
+
+    std::thread::scope(|scope| {
+        for _ in 0..2 {
+            let shared = Arc::clone(&shared);
+            scope.spawn(move || {
+                for _ in 0..10 {
+                    let contents = shared.read(b"/served\0".try_into().unwrap()).unwrap();
+                    assert_eq!(contents, [0x5a; 1000]);
+                }
+            });
+        }
+    });
+}